@@ -4,28 +4,81 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use chrono::{Duration, Utc};
-use http::HeaderValue;
+use crate::time::{self, Timestamp, TimeDelta};
+use http::{header::{AUTHORIZATION, CONTENT_TYPE, LINK, USER_AGENT}, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
 use jsonwebtoken::{Header, Algorithm, EncodingKey};
+#[cfg(feature = "reqwest")]
 use reqwest::Client;
-use serde::Serialize;
-use std::{fmt::Debug, ops::Add};
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+use reqwest::Proxy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use futures_core::Stream;
+use futures_util::TryStreamExt;
+use std::{collections::{HashMap, VecDeque}, fmt::Debug, future::Future, ops::Add, sync::{Arc, RwLock}};
 use tracing::debug;
 
-use crate::{GitHubInstallationAuthenticator, GitHubAuthenticatorError};
+use crate::{permissions::Permissions, token::{bearer_authorization, deserialize_granted_permissions}, GitHubHost, GitHubInstallationAuthenticator, Installation, Jwt, JwtConfig, InstallationManager, oauth::OAuthClient, TokenRequest, GitHubAuthenticatorError, HttpTransport, RequestFailure, AuditEvent, AuditSink, TracingAuditSink, ObservedRequest, RequestObserver, logging::{log_at, LoggingPolicy}};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{CircuitBreaker, RequestPacer, RetryPolicy};
 
-static GITHUB_API_BASE: &str = "https://api.github.com";
+static DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+static DEFAULT_INSTALLATION_CACHE_TTL: TimeDelta = time::minutes(10);
+static DEFAULT_JWT_DURATION: TimeDelta = time::seconds(60);
+// GitHub rejects JWTs that expire more than 10 minutes after they were issued
+pub(crate) static MAX_JWT_DURATION: TimeDelta = time::minutes(10);
+pub(crate) static DEFAULT_API_VERSION: &str = "2022-11-28";
+pub(crate) static X_GITHUB_API_VERSION: &str = "x-github-api-version";
 
-/// An authenticator for generating installation authenticators.
+// Accounts (keyed by lowercase login) and repositories (keyed by lowercase full name) mapped to the
+// id of the installation that has access to them along with the time at which the mapping should
+// be looked up again
+type InstallationIds = HashMap<String, (u32, Timestamp)>;
+
+/// An authenticator for generating installation authenticators. Clones share the transport,
+/// host and base uri of the original.
 #[derive(Clone)]
 pub struct GitHubAppAuthenticator {
-    inner: Client,
+    endpoint: Arc<RwLock<Endpoint>>,
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    client_settings: ClientSettings,
+    timeout: std::time::Duration,
+    installation_ids: Arc<RwLock<InstallationIds>>,
+    installation_cache_ttl: TimeDelta,
+    rate_limit: Arc<RwLock<Option<RateLimit>>>,
+    // The difference between GitHub's clock and the local clock, as measured from the responses to
+    // JWTs that GitHub rejected as not yet or no longer valid
+    clock_skew: Arc<RwLock<TimeDelta>>,
+    audit_sink: Arc<dyn AuditSink>,
+    observer: Option<Arc<dyn RequestObserver>>,
+    logging: LoggingPolicy,
+    #[cfg(not(target_arch = "wasm32"))]
+    pacer: Option<RequestPacer>,
+    #[cfg(not(target_arch = "wasm32"))]
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(not(target_arch = "wasm32"))]
+    retry_policy: Option<Arc<dyn RetryPolicy>>,
     app_id: u32,
     key: Vec<u8>,
-    base_endpoint: String,
+    // Whether JWTs are left unsigned, for apps that are only used with a simulated transport
+    pub(crate) simulated: bool,
+    jwt_duration: TimeDelta,
+    jwt_config: JwtConfig,
+    default_request: TokenRequest,
+    client_id: Option<String>,
+    api_version: HeaderValue,
     user_agent: HeaderValue,
 }
 
+// Where requests are sent to and how. Shared with all authenticators created from an
+// authenticator, so that changes made after creating them still apply to them
+#[derive(Clone)]
+struct Endpoint {
+    transport: Arc<dyn HttpTransport>,
+    host: GitHubHost,
+    base_endpoint: String,
+    default_headers: HeaderMap,
+}
+
 impl Debug for GitHubAppAuthenticator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("GitHubInstallationAuthenticator")
@@ -46,69 +99,815 @@ impl GitHubAppAuthenticator {
         debug!(?app_id, ?user_agent, "Creating app authenticator");
 
         Self {
-            inner: Client::new(),
+            endpoint: Arc::new(RwLock::new(Endpoint {
+                #[cfg(feature = "reqwest")]
+                transport: Arc::new(crate::ReqwestTransport::default()),
+                #[cfg(not(feature = "reqwest"))]
+                transport: Arc::new(crate::transport::MissingTransport),
+                host: GitHubHost::Dotcom,
+                base_endpoint: GitHubHost::Dotcom.api_endpoint(),
+                default_headers: HeaderMap::new(),
+            })),
+            #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+            client_settings: ClientSettings::default(),
+            timeout: DEFAULT_TIMEOUT,
+            installation_ids: Arc::new(RwLock::new(HashMap::new())),
+            installation_cache_ttl: DEFAULT_INSTALLATION_CACHE_TTL,
+            rate_limit: Arc::new(RwLock::new(None)),
+            clock_skew: Arc::new(RwLock::new(time::zero())),
+            audit_sink: Arc::new(TracingAuditSink),
+            observer: None,
+            logging: LoggingPolicy::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            pacer: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            circuit_breaker: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            retry_policy: None,
             app_id,
             key,
-            base_endpoint: GITHUB_API_BASE.to_string(),
+            simulated: false,
+            jwt_duration: DEFAULT_JWT_DURATION,
+            jwt_config: JwtConfig::default(),
+            default_request: TokenRequest::default(),
+            client_id: None,
+            api_version: HeaderValue::from_static(DEFAULT_API_VERSION),
             user_agent,
         }
     }
 
-    /// Configure the client to send requests via.
+    /// Configure the transport to send requests via. The transport is shared by all installation
+    /// authenticators created from this authenticator, including those created before the
+    /// transport was configured.
+    pub fn with_transport<T>(&mut self, transport: T) -> &mut Self where T: HttpTransport + 'static {
+        self.with_shared_transport(Arc::new(transport))
+    }
+
+    // Configure a transport that is already shared with other authenticators
+    pub(crate) fn with_shared_transport(&mut self, transport: Arc<dyn HttpTransport>) -> &mut Self {
+        self.endpoint.write().unwrap().transport = transport;
+        self
+    }
+
+    /// Configure the client to send requests via. The client is shared by all installation
+    /// authenticators created from this authenticator, including those created before the client
+    /// was configured.
+    #[cfg(feature = "reqwest")]
     pub fn with_client(&mut self, client: Client) -> &mut Self {
-        self.inner = client;
+        self.with_transport(crate::ReqwestTransport::new(client))
+    }
+
+    /// Configure a proxy to send all requests through, including those of installation
+    /// authenticators created from this authenticator. This replaces any client configured via
+    /// [`Self::with_client`]; to combine a proxy with other client settings configure the proxy
+    /// on that client instead.
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    pub fn with_proxy(&mut self, proxy: Proxy) -> Result<&mut Self, GitHubAuthenticatorError> {
+        self.client_settings.proxy = Some(proxy);
+        let client = self.client_settings.build()?;
+        Ok(self.with_client(client))
+    }
+
+    /// Configure how long to wait for a connection to GitHub to be established. Like
+    /// [`Self::with_proxy`], this replaces any client configured via [`Self::with_client`].
+    #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+    pub fn with_connect_timeout(&mut self, timeout: std::time::Duration) -> Result<&mut Self, GitHubAuthenticatorError> {
+        self.client_settings.connect_timeout = Some(timeout);
+        let client = self.client_settings.build()?;
+        Ok(self.with_client(client))
+    }
+
+    /// Configure how long to wait for any single request to GitHub to complete, including
+    /// establishing a connection and reading the response. Defaults to 30 seconds. The default
+    /// transport does not support timeouts on wasm targets, where the host environment is expected
+    /// to bound requests instead.
+    pub fn with_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Configure how long the JWTs that authenticate requests to GitHub App endpoints are valid
+    /// for. Defaults to 60 seconds. GitHub rejects JWTs that are valid for more than 10 minutes,
+    /// so longer durations fail all requests to app endpoints with
+    /// [`GitHubAuthenticatorError::JwtDurationTooLong`].
+    pub fn with_jwt_duration(&mut self, duration: TimeDelta) -> &mut Self {
+        self.jwt_duration = duration;
+        self
+    }
+
+    /// Configure a `kid` header and additional claims to add to the JWTs that authenticate
+    /// requests to GitHub App endpoints, see [`JwtConfig`].
+    pub fn with_jwt_config(&mut self, config: JwtConfig) -> &mut Self {
+        for name in config.ignored_claims() {
+            log_at!(self.logging.internal_errors(), name, "Ignoring reserved JWT claim");
+        }
+
+        self.jwt_config = config;
+        self
+    }
+
+    /// Configure the version of the REST API to request, which is sent as the
+    /// `X-GitHub-Api-Version` header with every request. Defaults to `2022-11-28`, so that the
+    /// behavior of the API does not change when GitHub changes its default version.
+    pub fn with_api_version(&mut self, version: HeaderValue) -> &mut Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Get the version of the REST API that is requested.
+    pub fn api_version(&self) -> HeaderValue {
+        self.api_version.clone()
+    }
+
+    /// Configure the token request that installation authenticators of this app use by default,
+    /// see [`Self::default_request`].
+    pub fn with_default_request(&mut self, request: TokenRequest) -> &mut Self {
+        self.default_request = request;
+        self
+    }
+
+    /// Get the token request that installation authenticators of this app use by default, with
+    /// [`GitHubInstallationAuthenticator::into_refreshing_default`]. Defaults to a request for all
+    /// of the permissions and repositories of an installation.
+    pub fn default_request(&self) -> &TokenRequest {
+        &self.default_request
+    }
+
+    /// Configure the client id of the app, which is listed on the settings page of the app and is
+    /// only needed for acting on behalf of users, see [`Self::oauth_client`].
+    pub fn with_client_id(&mut self, client_id: String) -> &mut Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// Get the client id of the app, if configured.
+    pub fn client_id(&self) -> Option<&str> {
+        self.client_id.as_deref()
+    }
+
+    /// Configure how long the installation that has access to a repository or account is
+    /// remembered for by [`Self::installation_authenticator_for_repo`] and
+    /// [`InstallationManager`](crate::InstallationManager). Defaults to 10 minutes.
+    pub fn with_installation_cache_ttl(&mut self, ttl: TimeDelta) -> &mut Self {
+        self.installation_cache_ttl = ttl;
+        self
+    }
+
+    /// Configure a pacer that limits the concurrency and rate of all requests sent on behalf of
+    /// the app, including those of installation authenticators created from this authenticator
+    /// afterwards. Useful for minting tokens for many installations without tripping GitHub's
+    /// secondary rate limits.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_pacer(&mut self, pacer: RequestPacer) -> &mut Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    /// Configure a circuit breaker that fails installation token requests fast while GitHub is
+    /// unavailable. The breaker applies to installation authenticators created from this
+    /// authenticator afterwards.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_circuit_breaker(&mut self, breaker: CircuitBreaker) -> &mut Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// The circuit breaker that installation token requests are sent through, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_ref()
+    }
+
+    /// Configure a policy for retrying failed installation token requests, such as
+    /// [`ExponentialBackoff`](crate::ExponentialBackoff). The policy applies to installation
+    /// authenticators created from this authenticator afterwards. Failed requests are not retried
+    /// by default.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_retry_policy<P>(&mut self, policy: P) -> &mut Self where P: RetryPolicy + 'static {
+        self.retry_policy = Some(Arc::new(policy));
         self
     }
 
-    /// Configure base uri of the API to send requests to.
+    // Get the policy for retrying failed installation token requests
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn retry_policy(&self) -> Option<&dyn RetryPolicy> {
+        self.retry_policy.as_deref()
+    }
+
+    /// Configure the sink that receives a record of every installation token issued or revoked by
+    /// installation authenticators created from this authenticator afterwards. Defaults to
+    /// [`TracingAuditSink`].
+    pub fn with_audit_sink<S>(&mut self, sink: S) -> &mut Self where S: AuditSink + 'static {
+        self.audit_sink = Arc::new(sink);
+        self
+    }
+
+    /// Configure an observer that is invoked with a sanitized view of every request sent by this
+    /// authenticator and by installation authenticators created from it afterwards.
+    pub fn with_request_observer<O>(&mut self, observer: O) -> &mut Self where O: RequestObserver + 'static {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Configure what is logged about requests sent by this authenticator and by installation
+    /// authenticators created from it afterwards.
+    pub fn with_logging_policy(&mut self, policy: LoggingPolicy) -> &mut Self {
+        self.logging = policy;
+        self
+    }
+
+    // Get the policy for logging requests
+    pub(crate) fn logging(&self) -> &LoggingPolicy {
+        &self.logging
+    }
+
+    // Record an event with the configured audit sink
+    pub(crate) fn audit(&self, event: AuditEvent) {
+        self.audit_sink.record(&event);
+    }
+
+    /// Configure the GitHub deployment that the app is registered on. This determines the
+    /// endpoints that requests are sent to. GitHub Enterprise Server instances that use a private
+    /// certificate authority additionally require a client configured via [`Self::with_client`].
+    /// Like the transport, the host applies to all authenticators created from this
+    /// authenticator, and replaces any base uri configured via [`Self::with_base_uri`].
+    pub fn with_host(&mut self, host: GitHubHost) -> &mut Self {
+        let mut endpoint = self.endpoint.write().unwrap();
+        endpoint.base_endpoint = host.api_endpoint();
+        endpoint.host = host;
+        drop(endpoint);
+
+        self
+    }
+
+    /// Configure headers to send with every request, for instance headers that a gateway in front
+    /// of a GitHub Enterprise Server instance requires. Headers that the crate sets itself, such as
+    /// the authorization header, take precedence. Like the transport, the headers apply to all
+    /// authenticators created from this authenticator.
+    pub fn with_default_headers(&mut self, headers: HeaderMap) -> &mut Self {
+        self.endpoint.write().unwrap().default_headers = headers;
+        self
+    }
+
+    /// Configure base uri of the API to send requests to. Like the transport, the base uri
+    /// applies to all authenticators created from this authenticator.
     pub fn with_base_uri<T>(&mut self, base_endpoint: T) -> &mut Self where T: ToString {
-        self.base_endpoint = base_endpoint.to_string();
+        self.endpoint.write().unwrap().base_endpoint = base_endpoint.to_string();
         self
     }
 
-    /// Generate a new JWT for calling GitHub App endpoints.
-    pub fn generate_jwt(&self, duration: Duration) -> Result<String, GitHubAuthenticatorError> {
+    /// Generate a new JWT for calling GitHub App endpoints that is valid for `duration`. The claims
+    /// of the JWT are adjusted by the measured [`Self::clock_skew`]. Fails with
+    /// [`GitHubAuthenticatorError::JwtDurationTooLong`] if `duration` exceeds the 10 minutes that
+    /// GitHub accepts.
+    pub fn generate_jwt(&self, duration: TimeDelta) -> Result<Jwt, GitHubAuthenticatorError> {
+        if duration > MAX_JWT_DURATION {
+            return Err(GitHubAuthenticatorError::JwtDurationTooLong(duration));
+        }
+
+        crate::metrics::jwt_generated(self.app_id);
+
+        let issued_at = time::now();
+        let now = issued_at.add(self.clock_skew());
         let claims = GitHubAppClaims {
-            iat: Utc::now().timestamp(),
-            exp: Utc::now().add(duration).timestamp(),
+            iat: time::unix_seconds(now),
+            exp: time::unix_seconds(now.add(duration)),
             iss: self.app_id,
+            additional: self.jwt_config.claims(),
         };
 
+        if self.simulated {
+            return crate::simulation::unsigned_jwt(&claims).map(|token| Jwt::new(token, issued_at, issued_at.add(duration)));
+        }
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = self.jwt_config.kid().map(str::to_string);
+
         jsonwebtoken::encode(
-            &Header::new(Algorithm::RS256),
+            &header,
             &claims,
             &EncodingKey::from_rsa_pem(&self.key).map_err(|err| {
-                tracing::error!(?err, "Failed to create JWT key");
+                log_at!(self.logging.internal_errors(), ?err, "Failed to create JWT key");
                 GitHubAuthenticatorError::FailedToParseKey
             })?,
         )
         .map_err(|err| {
-            tracing::error!(?claims, ?err, "Failed to generate authentication JWT");
+            log_at!(self.logging.internal_errors(), iss = %self.logging.id(claims.iss), iat = claims.iat, exp = claims.exp, ?err, "Failed to generate authentication JWT");
             GitHubAuthenticatorError::FailedToGenerateJwt(err)
         })
+        .map(|token| Jwt::new(token, issued_at, issued_at.add(duration)))
+    }
+
+    /// Create an Authorization header value that authenticates requests to GitHub App endpoints
+    /// with a newly generated JWT. The value is marked as sensitive so that it is omitted from
+    /// debug output.
+    pub fn jwt_authorization_header(&self, duration: TimeDelta) -> Result<HeaderValue, GitHubAuthenticatorError> {
+        self.generate_jwt(duration)?.authorization_header()
     }
 
     /// Generate an installation authenticator. Each installation authenticator receives its own
-    /// copy of the app authenticator. Internal JWT credentials are not shared are not shared across
-    /// installation authenticators.
+    /// copy of the app authenticator, and JWTs are not shared across installation authenticators.
+    /// The transport (including a client or proxy), host, base uri and default headers are shared,
+    /// so that configuring them afterwards applies to existing installation authenticators as
+    /// well. All other settings, such as the timeout, API version, JWT duration, logging policy,
+    /// retry policy and request observer, are copied when the installation authenticator is
+    /// created and are not affected by later changes to this authenticator.
     pub fn installation_authenticator(&self, installation_id: u32) -> GitHubInstallationAuthenticator {
         GitHubInstallationAuthenticator::new(self.clone(), installation_id)
     }
 
-    // Get the user agent header.
+    /// Fetch the metadata of the app that this authenticator authenticates as. This also serves
+    /// as a check that the app id and key are accepted by GitHub.
+    pub async fn app(&self) -> Result<App, GitHubAuthenticatorError> {
+        self.get("/app").await
+    }
+
+    /// Fetch all of the installations of the app. Installations are requested 100 at a time.
+    pub async fn installations(&self) -> Result<Vec<Installation>, GitHubAuthenticatorError> {
+        self.installations_stream(100).try_collect().await
+    }
+
+    /// Stream the installations of the app, requesting `page_size` installations at a time. The
+    /// next page is only requested once all installations of the current page have been consumed.
+    /// GitHub allows for at most 100 installations per page.
+    pub fn installations_stream(&self, page_size: u8) -> impl Stream<Item = Result<Installation, GitHubAuthenticatorError>> + '_ {
+        self.paginate(format!("{}/app/installations?per_page={}", self.base_endpoint(), page_size.clamp(1, 100)))
+    }
+
+    // Stream the items of a paginated app endpoint, starting at the full url of the first page
+    pub(crate) fn paginate<T>(&self, first: String) -> impl Stream<Item = Result<T, GitHubAuthenticatorError>> + '_
+    where
+        T: DeserializeOwned + 'static,
+    {
+        futures_util::stream::try_unfold(
+            (VecDeque::new(), Some(first)),
+            move |(mut page, mut next): (VecDeque<T>, Option<String>)| async move {
+                while page.is_empty() {
+                    match next {
+                        Some(url) => {
+                            let (items, next_page) = self.get_page(&url).await?;
+                            page = items;
+                            next = next_page;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+
+                Ok(page.pop_front().map(|item| (item, (page, next))))
+            },
+        )
+    }
+
+    /// Fetch an installation of the app by its id. This can be used to check whether an
+    /// installation is suspended before requesting tokens for it.
+    pub async fn installation(&self, installation_id: u32) -> Result<Installation, GitHubAuthenticatorError> {
+        self.get(&format!("/app/installations/{}", installation_id)).await
+    }
+
+    /// Fetch the installation of the app that has access to the repository `owner/repo`. Fails
+    /// with [`GitHubAuthenticatorError::NotInstalled`] if no installation has access.
+    pub async fn installation_for_repo(&self, owner: &str, repo: &str) -> Result<Installation, GitHubAuthenticatorError> {
+        self.find_installation(&format!("/repos/{}/{}/installation", owner, repo), format!("{}/{}", owner, repo))
+            .await
+    }
+
+    /// Create a client for the OAuth flows of the app, which authenticate as users of the app
+    /// rather than as the app itself. The client secret is listed on the settings page of the
+    /// app, and the client id must have been configured with [`Self::with_client_id`]. The client
+    /// shares the configuration of this authenticator.
+    pub fn oauth_client(&self, client_secret: String) -> Result<OAuthClient, GitHubAuthenticatorError> {
+        let client_id = self.client_id.clone().ok_or_else(|| {
+            GitHubAuthenticatorError::InvalidConfiguration("a client id is required for OAuth flows".to_string())
+        })?;
+
+        Ok(OAuthClient::for_app(self.for_app(0, vec![]), client_id, client_secret))
+    }
+
+    /// Create a manager that hands out refreshing authenticators for any installation of the app,
+    /// all of which fetch tokens for `request`. At most `capacity` authenticators are kept alive.
+    pub fn installation_manager(&self, request: TokenRequest, capacity: usize) -> InstallationManager {
+        InstallationManager::new(self.clone(), request, capacity)
+    }
+
+    /// Generate an installation authenticator for the installation that has access to a repository,
+    /// given as `owner/repo`. The installation is looked up via [`Self::installation_for_repo`]
+    /// and remembered for subsequent calls. See [`Self::with_installation_cache_ttl`].
+    pub async fn installation_authenticator_for_repo(&self, full_name: &str) -> Result<GitHubInstallationAuthenticator, GitHubAuthenticatorError> {
+        let installation_id = self.repository_installation_id(full_name).await?;
+        Ok(self.installation_authenticator(installation_id))
+    }
+
+    // Look up the id of the installation that has access to a repository, given as `owner/repo`
+    pub(crate) async fn repository_installation_id(&self, full_name: &str) -> Result<u32, GitHubAuthenticatorError> {
+        let (owner, repo) = full_name
+            .split_once('/')
+            .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty() && !repo.contains('/'))
+            .ok_or_else(|| GitHubAuthenticatorError::InvalidRepositoryName(full_name.to_string()))?;
+
+        self.cached_installation_id(full_name, self.installation_for_repo(owner, repo))
+            .await
+    }
+
+    // Look up the id of the installation on an organization
+    pub(crate) async fn org_installation_id(&self, org: &str) -> Result<u32, GitHubAuthenticatorError> {
+        self.cached_installation_id(org, self.installation_for_org(org))
+            .await
+    }
+
+    // Logins and repository names are case insensitive, and can not collide as logins never contain
+    // a slash
+    async fn cached_installation_id<F>(&self, target: &str, lookup: F) -> Result<u32, GitHubAuthenticatorError>
+    where
+        F: Future<Output = Result<Installation, GitHubAuthenticatorError>>,
+    {
+        let key = target.to_lowercase();
+        let cached = self
+            .installation_ids
+            .read()
+            .unwrap()
+            .get(&key)
+            .filter(|(_, expires_at)| *expires_at > time::now())
+            .map(|(installation_id, _)| *installation_id);
+
+        match cached {
+            Some(installation_id) => Ok(installation_id),
+            None => {
+                let installation_id = lookup.await?.id;
+
+                let now = time::now();
+                let mut installation_ids = self.installation_ids.write().unwrap();
+                installation_ids.retain(|_, (_, expires_at)| *expires_at > now);
+                installation_ids.insert(key, (installation_id, now + self.installation_cache_ttl));
+
+                Ok(installation_id)
+            }
+        }
+    }
+
+    /// Fetch the installation of the app on an organization. Fails with
+    /// [`GitHubAuthenticatorError::NotInstalled`] if the app is not installed on the organization.
+    pub async fn installation_for_org(&self, org: &str) -> Result<Installation, GitHubAuthenticatorError> {
+        self.find_installation(&format!("/orgs/{}/installation", org), org.to_string())
+            .await
+    }
+
+    /// Fetch the installation of the app on a user account. Fails with
+    /// [`GitHubAuthenticatorError::NotInstalled`] if the app is not installed on the account.
+    pub async fn installation_for_user(&self, username: &str) -> Result<Installation, GitHubAuthenticatorError> {
+        self.find_installation(&format!("/users/{}/installation", username), username.to_string())
+            .await
+    }
+
+    // GitHub responds with a 404 when the app is not installed on the target
+    async fn find_installation(&self, path: &str, target: String) -> Result<Installation, GitHubAuthenticatorError> {
+        match self.get(path).await {
+            Err(GitHubAuthenticatorError::AppRequestFailed(failure)) if failure.status == StatusCode::NOT_FOUND => {
+                Err(GitHubAuthenticatorError::NotInstalled(target))
+            }
+            result => result,
+        }
+    }
+
+    /// The id of the app that this authenticator authenticates as.
+    pub fn app_id(&self) -> u32 {
+        self.app_id
+    }
+
+    // Create an authenticator for a different app that shares this authenticator's configuration
+    pub(crate) fn for_app(&self, app_id: u32, key: Vec<u8>) -> Self {
+        Self {
+            installation_ids: Arc::new(RwLock::new(HashMap::new())),
+            rate_limit: Arc::new(RwLock::new(None)),
+            app_id,
+            key,
+            ..self.clone()
+        }
+    }
+
+    /// The rate limit of requests authenticated as the app, as reported by the most recent
+    /// response to such a request. Shared by all installation authenticators created from this
+    /// authenticator, as their token requests count against the same limit.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.read().unwrap().clone()
+    }
+
+    // Remember the rate limit reported by a response to a request authenticated as the app
+    pub(crate) fn record_rate_limit(&self, headers: &HeaderMap) -> Option<RateLimit> {
+        let rate_limit = RateLimit::from_headers(headers)?;
+        *self.rate_limit.write().unwrap() = Some(rate_limit.clone());
+        Some(rate_limit)
+    }
+
+    /// The difference between GitHub's clock and the local clock. A positive skew means that the
+    /// local clock is behind. The skew is measured whenever GitHub rejects a JWT because its claims
+    /// are not yet or no longer valid, and is zero until then. Shared by all installation
+    /// authenticators created from this authenticator.
+    pub fn clock_skew(&self) -> TimeDelta {
+        *self.clock_skew.read().unwrap()
+    }
+
+    // Measure the clock skew from the Date header of a response that rejected a JWT due to its
+    // claims. Returns whether the skew changed, in which case a new JWT may be accepted
+    fn record_clock_skew(&self, response: &Response<Vec<u8>>) -> bool {
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return false;
+        }
+
+        // GitHub rejects such JWTs with e.g. `'Expiration time' claim ('exp') is too far in the
+        // future` or `'Issued at' claim ('iat') must be an Integer representing a time in the past`
+        let failure = RequestFailure::from_response(response);
+        let rejected_claims = failure
+            .message
+            .as_ref()
+            .map(|message| message.contains("('exp')") || message.contains("('iat')"))
+            .unwrap_or(false);
+
+        let date = match failure.date {
+            Some(date) if rejected_claims => date,
+            _ => return false,
+        };
+
+        // The Date header only has a resolution of seconds
+        let skew = time::seconds(time::whole_seconds(time::between(date, time::now())));
+        let previous = std::mem::replace(&mut *self.clock_skew.write().unwrap(), skew);
+
+        log_at!(self.logging.request_failures(), skew = time::whole_seconds(skew), previous = time::whole_seconds(previous), request_id = ?failure.request_id, "GitHub rejected JWT claims due to clock skew");
+
+        skew != previous
+    }
+
+    /// Get the user agent header that is sent with requests.
     pub fn user_agent(&self) -> HeaderValue {
         self.user_agent.clone()
     }
 
+    /// Get the GitHub deployment that the app is registered on.
+    pub fn host(&self) -> GitHubHost {
+        self.endpoint.read().unwrap().host.clone()
+    }
+
     // Get the base API endpoint.
-    pub(crate) fn base_endpoint(&self) -> &str {
-        &self.base_endpoint
+    pub(crate) fn base_endpoint(&self) -> String {
+        self.endpoint.read().unwrap().base_endpoint.clone()
+    }
+
+    // Perform a GET request against an app endpoint authenticated via a newly generated JWT.
+    pub(crate) async fn get<T>(&self, path: &str) -> Result<T, GitHubAuthenticatorError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}{}", self.base_endpoint(), path);
+        Ok(self.get_page(&url).await?.0)
+    }
+
+    // Perform a GET request against the full url of an app endpoint authenticated via a newly
+    // generated JWT. Along with the response, the url of the next page is returned for paginated
+    // endpoints.
+    pub(crate) async fn get_page<T>(&self, url: &str) -> Result<(T, Option<String>), GitHubAuthenticatorError>
+    where
+        T: DeserializeOwned,
+    {
+        let response = self.send_with_jwt(Method::GET, url, None).await?;
+
+        self.record_rate_limit(response.headers());
+
+        if response.status() == StatusCode::OK {
+            let body = serde_json::from_slice(response.body()).map_err(|err| {
+                log_at!(self.logging.internal_errors(), ?err, url = %self.logging.id(url), "Failed to decode app response body");
+                GitHubAuthenticatorError::app_response_decode(err, response.body())
+            })?;
+
+            Ok((body, next_page(response.headers())))
+        } else {
+            let status = response.status();
+            let body = self.logging.body(response.body());
+            let failure = RequestFailure::from_response(&response);
+
+            log_at!(self.logging.request_failures(), ?status, ?body, url = %self.logging.id(url), request_id = ?failure.request_id, "App request failed");
+
+            Err(GitHubAuthenticatorError::AppRequestFailed(Box::new(failure)))
+        }
+    }
+
+    // Send a request to an app endpoint authenticated via a newly generated JWT. Unlike
+    // `get`, the status of the response is left for the caller to check.
+    pub(crate) async fn send_as_app(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        let url = format!("{}{}", self.base_endpoint(), path);
+
+        self.send_with_jwt(method, &url, body).await
+    }
+
+    // Send a request to the full url of an app endpoint authenticated via a newly generated JWT.
+    // If GitHub rejects the JWT because the local clock is skewed, the request is sent once more
+    // with a JWT whose claims account for the skew.
+    pub(crate) async fn send_with_jwt(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        let jwt = self.generate_jwt(self.jwt_duration)?;
+        let response = self.send(method.clone(), url, Some(jwt.as_str()), body.clone()).await?;
+
+        if self.record_clock_skew(&response) {
+            let jwt = self.generate_jwt(self.jwt_duration)?;
+            self.send(method, url, Some(jwt.as_str()), body).await
+        } else {
+            Ok(response)
+        }
+    }
+
+    // Send a request via the configured transport, authenticated by the given bearer token if
+    // any. A body, if any, must be JSON.
+    pub(crate) async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        bearer: Option<&str>,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(url)
+            .header(USER_AGENT, self.user_agent());
+
+        if let Some(bearer) = bearer {
+            builder = builder.header(AUTHORIZATION, bearer_authorization(bearer)?);
+        }
+
+        if body.is_some() {
+            builder = builder.header(CONTENT_TYPE, "application/json");
+        }
+
+        let request = builder
+            .body(body.unwrap_or_default())
+            .map_err(|err| GitHubAuthenticatorError::Transport(Box::new(err)))?;
+
+        self.dispatch(request).await
+    }
+
+    // Send a fully built request via the configured transport, requesting the configured API
+    // version and adding the default headers unless the request already specifies them. With the `opentelemetry` feature, the
+    // trace context of the current span is propagated to GitHub
+    pub(crate) async fn dispatch(&self, mut request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        request
+            .headers_mut()
+            .entry(X_GITHUB_API_VERSION)
+            .or_insert_with(|| self.api_version.clone());
+
+        let (transport, default_headers) = {
+            let endpoint = self.endpoint.read().unwrap();
+            (endpoint.transport.clone(), endpoint.default_headers.clone())
+        };
+
+        let headers = request.headers_mut();
+
+        for name in default_headers.keys() {
+            if !headers.contains_key(name) {
+                for value in default_headers.get_all(name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+
+        #[cfg(feature = "opentelemetry")]
+        crate::otel::inject_context(request.headers_mut());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let _permit = match &self.pacer {
+            Some(pacer) => Some(pacer.acquire().await),
+            None => None,
+        };
+
+        let observed = self
+            .observer
+            .as_ref()
+            .map(|observer| (observer, request.method().clone(), request.uri().path().to_string(), time::now()));
+
+        let result = transport.send(request, self.timeout).await;
+
+        if let Some((observer, method, path, started)) = observed {
+            let latency = time::to_std(time::between(time::now(), started));
+            observer.observe(&ObservedRequest::new(method, &path, &result, latency));
+        }
+
+        result
+    }
+}
+
+// Settings used to build a client when the caller has not provided their own
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+#[derive(Clone, Debug, Default)]
+struct ClientSettings {
+    proxy: Option<Proxy>,
+    connect_timeout: Option<std::time::Duration>,
+}
+
+#[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+impl ClientSettings {
+    fn build(&self) -> Result<Client, reqwest::Error> {
+        let mut builder = Client::builder();
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        builder.build()
+    }
+}
+
+// Find the url of the next page in a Link header of the form `<url>; rel="next", <url>; rel="last"`
+fn next_page(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(LINK)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find_map(|link| {
+            let (url, params) = link.split_once(';')?;
+            params
+                .split(';')
+                .any(|param| param.trim() == "rel=\"next\"")
+                .then(|| url.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+        })
+}
+
+/// The state of a rate limit, as reported by the `X-RateLimit-*` headers of a response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The maximum number of requests per hour.
+    pub limit: u32,
+    /// The number of requests remaining in the current window.
+    pub remaining: u32,
+    /// The number of requests made in the current window.
+    pub used: Option<u32>,
+    /// The time at which the current window ends and the remaining requests are reset.
+    pub reset: Timestamp,
+    /// The rate limit resource that the request counted against, e.g. `core`.
+    pub resource: Option<String>,
+}
+
+impl RateLimit {
+    // Responses that do not count against a rate limit lack the headers
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        Some(Self {
+            limit: header("x-ratelimit-limit")?.parse().ok()?,
+            remaining: header("x-ratelimit-remaining")?.parse().ok()?,
+            used: header("x-ratelimit-used").and_then(|used| used.parse().ok()),
+            reset: time::from_unix_seconds(header("x-ratelimit-reset")?.parse().ok()?)?,
+            resource: header("x-ratelimit-resource").map(str::to_string),
+        })
     }
 }
 
 #[derive(Debug, Serialize)]
-struct GitHubAppClaims {
+struct GitHubAppClaims<'a> {
     iat: i64,
     exp: i64,
     iss: u32,
+    // Never contains the claims above, see JwtConfig::with_claim
+    #[serde(flatten)]
+    additional: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+/// The metadata of a GitHub App.
+#[derive(Clone, Debug, Deserialize)]
+pub struct App {
+    pub id: u64,
+    /// The url-friendly name of the app. The app's bot user is named `<slug>[bot]`.
+    pub slug: Option<String>,
+    pub name: String,
+    /// The user or organization that owns the app.
+    pub owner: Option<Account>,
+    pub description: Option<String>,
+    pub html_url: String,
+    /// The permissions that the app requests from installations. Individual permissions are
+    /// omitted if GitHub reports them at a level that this crate does not understand.
+    #[serde(default, deserialize_with = "deserialize_granted_permissions")]
+    pub permissions: Option<Permissions>,
+    /// The webhook events that the app subscribes to.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub installations_count: Option<u64>,
+}
+
+/// A GitHub user or organization.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Account {
+    pub id: u64,
+    pub login: String,
+    /// Either `User` or `Organization`.
+    #[serde(rename = "type")]
+    pub account_type: String,
 }
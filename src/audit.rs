@@ -0,0 +1,82 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{Timestamp};
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+
+use crate::TokenRequest;
+
+/// Receives a record of every installation token that is issued or revoked. A sink configured via
+/// [`GitHubAppAuthenticator::with_audit_sink`](crate::GitHubAppAuthenticator::with_audit_sink)
+/// applies to all installation authenticators of the app. Sinks are invoked synchronously after
+/// GitHub has confirmed the issuance or revocation, and should hand events off rather than block.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+/// An entry in the audit record of installation tokens. Tokens are identified by their
+/// [`token_fingerprint`] rather than by the token itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// GitHub issued an installation token.
+    TokenIssued {
+        app_id: u32,
+        installation_id: u32,
+        /// The permissions and repositories that the token was requested for.
+        request: TokenRequest,
+        fingerprint: String,
+        issued_at: Timestamp,
+        expires_at: Timestamp,
+    },
+    /// GitHub revoked an installation token.
+    TokenRevoked {
+        app_id: u32,
+        installation_id: u32,
+        fingerprint: String,
+        revoked_at: Timestamp,
+    },
+}
+
+/// The default [`AuditSink`], which logs events at the info level with the
+/// `github_app_authenticator::audit` target.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        match event {
+            AuditEvent::TokenIssued { app_id, installation_id, request, fingerprint, issued_at, expires_at } => {
+                tracing::info!(
+                    target: "github_app_authenticator::audit",
+                    ?app_id,
+                    ?installation_id,
+                    ?request,
+                    ?fingerprint,
+                    ?issued_at,
+                    ?expires_at,
+                    "Installation access token issued"
+                );
+            }
+            AuditEvent::TokenRevoked { app_id, installation_id, fingerprint, revoked_at } => {
+                tracing::info!(
+                    target: "github_app_authenticator::audit",
+                    ?app_id,
+                    ?installation_id,
+                    ?fingerprint,
+                    ?revoked_at,
+                    "Installation access token revoked"
+                );
+            }
+        }
+    }
+}
+
+/// Compute a fingerprint that identifies a token without revealing it, in the form
+/// `sha256:<hex digest>`.
+pub fn token_fingerprint(token: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(token.as_bytes())))
+}
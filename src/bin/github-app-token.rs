@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Mint an installation access token from the command line, for instance from a shell based CI
+//! step:
+//!
+//! ```sh
+//! export GITHUB_APP_ID=12345
+//! export GITHUB_APP_PRIVATE_KEY_FILE=app.pem
+//! github-app-token --repo owner/repo --permissions contents=read --env >> "$GITHUB_ENV"
+//! ```
+
+use clap::{ArgGroup, Parser};
+use github_app_authenticator::{headers::HeaderValue, permissions::Permissions, GitHubAppAuthenticator, GitHubHost, TokenRequest};
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(name = "github-app-token", version, about = "Mint a GitHub App installation access token")]
+#[command(group(ArgGroup::new("key").required(true).args(["private_key", "private_key_file"])))]
+#[command(group(ArgGroup::new("installation").required(true).args(["installation_id", "repo"])))]
+struct Args {
+    /// The id of the app.
+    #[arg(long, env = "GITHUB_APP_ID")]
+    app_id: u32,
+    /// The private key of the app in PEM format.
+    #[arg(long, env = "GITHUB_APP_PRIVATE_KEY", hide_env_values = true)]
+    private_key: Option<String>,
+    /// A file containing the private key of the app in PEM format.
+    #[arg(long, env = "GITHUB_APP_PRIVATE_KEY_FILE")]
+    private_key_file: Option<std::path::PathBuf>,
+    /// The id of the installation to mint a token for.
+    #[arg(long, env = "GITHUB_APP_INSTALLATION_ID")]
+    installation_id: Option<u32>,
+    /// A repository, given as owner/name, whose installation to mint a token for.
+    #[arg(long)]
+    repo: Option<String>,
+    /// The permissions to grant the token, e.g. contents=read,issues=write. Defaults to all of the
+    /// permissions of the installation.
+    #[arg(long)]
+    permissions: Option<Permissions>,
+    /// The names of the repositories to scope the token to, e.g. repo-a,repo-b. Defaults to all
+    /// repositories of the installation.
+    #[arg(long, value_delimiter = ',')]
+    repositories: Option<Vec<String>>,
+    /// The GitHub deployment that the app is registered on: github.com, the subdomain of a ghe.com
+    /// enterprise such as octocorp.ghe.com, or the url of a GitHub Enterprise Server instance.
+    #[arg(long, env = "GITHUB_HOST", default_value = "github.com")]
+    host: GitHubHost,
+    /// Print the token as GITHUB_TOKEN=<token>, as expected by e.g. $GITHUB_ENV.
+    #[arg(long)]
+    env: bool,
+}
+
+async fn run(args: Args) -> Result<String, Box<dyn std::error::Error>> {
+    let key = match (args.private_key, args.private_key_file) {
+        (Some(key), _) => key.into_bytes(),
+        (None, Some(path)) => std::fs::read(&path)
+            .map_err(|err| format!("Failed to read private key from {}: {}", path.display(), err))?,
+        (None, None) => unreachable!("clap requires a private key"),
+    };
+
+    let mut app = GitHubAppAuthenticator::new(
+        args.app_id,
+        key,
+        HeaderValue::from_static(concat!("github-app-token/", env!("CARGO_PKG_VERSION"))),
+    );
+    app.with_host(args.host);
+
+    let authenticator = match (args.installation_id, args.repo) {
+        (Some(installation_id), _) => app.installation_authenticator(installation_id),
+        (None, Some(repo)) => app.installation_authenticator_for_repo(&repo).await?,
+        (None, None) => unreachable!("clap requires an installation"),
+    };
+
+    let request = TokenRequest {
+        permissions: args.permissions,
+        repositories: args.repositories,
+        ..Default::default()
+    };
+
+    Ok(authenticator.access_token(&request).await?)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let env = args.env;
+
+    match run(args).await {
+        Ok(token) if env => {
+            println!("GITHUB_TOKEN={}", token);
+            ExitCode::SUCCESS
+        }
+        Ok(token) => {
+            println!("{}", token);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("github-app-token: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time;
+use std::{future::Future, sync::OnceLock};
+use tokio::runtime::Runtime;
+
+use crate::{GitHubAuthenticatorError, GitHubInstallationAuthenticator, RefreshingGitHubInstallationAuthenticator, TokenRequest};
+
+// Drive a future to completion on a runtime that is shared by all blocking calls. Clients pool
+// their connections on the runtime that opened them, so the runtime must outlive any single call,
+// and a worker thread keeps the pooled connections serviced between calls. Panics if called from
+// within an async runtime.
+fn block_on<F, T>(future: F) -> Result<T, GitHubAuthenticatorError>
+where
+    F: Future<Output = Result<T, GitHubAuthenticatorError>>,
+{
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+    if RUNTIME.get().is_none() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("github-app-authenticator-blocking")
+            .enable_all()
+            .build()
+            .map_err(GitHubAuthenticatorError::FailedToStartRuntime)?;
+
+        // Another thread may have won the race to create the runtime, in which case ours is unused
+        let _ = RUNTIME.set(runtime);
+    }
+
+    RUNTIME.get().expect("runtime was initialized").block_on(future)
+}
+
+impl GitHubInstallationAuthenticator {
+    /// Fetch a new access token for a given request on this installation, blocking the current
+    /// thread until the request completes. This must not be called from within an async runtime.
+    pub fn access_token_blocking(&self, request: &TokenRequest) -> Result<String, GitHubAuthenticatorError> {
+        block_on(self.access_token(request))
+    }
+}
+
+impl RefreshingGitHubInstallationAuthenticator {
+    /// Fetch an updated access token for the configured request, blocking the current thread if
+    /// a new token needs to be requested. This must not be called from within an async runtime.
+    pub fn access_token_blocking(&self) -> Result<String, GitHubAuthenticatorError> {
+        match self.cached_token(time::zero()) {
+            Some(token) => Ok(token.token),
+            None => block_on(self.access_token()),
+        }
+    }
+}
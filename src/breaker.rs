@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::{future::Future, sync::{Arc, Mutex}, time::Duration};
+use tokio::time::Instant;
+
+use crate::{logging::{log_at, LoggingPolicy}, GitHubAuthenticatorError};
+
+/// Stops sending installation token requests while GitHub is unavailable, so that callers fail
+/// fast with [`GitHubAuthenticatorError::CircuitOpen`] instead of each waiting on a request that
+/// is bound to fail. A breaker configured via
+/// [`GitHubAppAuthenticator::with_circuit_breaker`](crate::GitHubAppAuthenticator::with_circuit_breaker)
+/// applies to the token requests of all installation authenticators of the app. Cloning is cheap,
+/// and all clones share the same state.
+///
+/// The breaker opens after a number of consecutive retryable failures, such as connection
+/// failures or server errors. Once open, a single probe request is let through after a cool down.
+/// A successful probe closes the breaker again, while a failed probe restarts the cool down.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    state: Arc<Mutex<BreakerState>>,
+    failure_threshold: u32,
+    cool_down: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // A probe request is in flight
+    HalfOpen,
+}
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests fail without being sent.
+    Open,
+    /// A single probe request has been sent to check whether GitHub is available again, while
+    /// other requests fail without being sent.
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    /// Create a breaker that opens after `failure_threshold` consecutive failures and lets a probe
+    /// request through once it has been open for `cool_down`.
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BreakerState::Closed { failures: 0 })),
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+        }
+    }
+
+    /// The current state of the breaker.
+    pub fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            BreakerState::Closed { .. } => CircuitState::Closed,
+            BreakerState::Open { until } if until <= Instant::now() => CircuitState::HalfOpen,
+            BreakerState::Open { .. } => CircuitState::Open,
+            BreakerState::HalfOpen => CircuitState::HalfOpen,
+        }
+    }
+
+    // Send a request unless the breaker is open, and record its outcome
+    pub(crate) async fn call<F, T>(&self, logging: &LoggingPolicy, request: F) -> Result<T, GitHubAuthenticatorError>
+    where
+        F: Future<Output = Result<T, GitHubAuthenticatorError>>,
+    {
+        let mut probe = self.admit(logging)?;
+        let result = request.await;
+
+        // Responses that GitHub will keep sending, such as a missing installation, still show
+        // that GitHub is available
+        let failed = result.as_ref().err().map(GitHubAuthenticatorError::is_retryable).unwrap_or(false);
+        probe.finish(failed);
+
+        result
+    }
+
+    fn admit<'a>(&'a self, logging: &'a LoggingPolicy) -> Result<Probe<'a>, GitHubAuthenticatorError> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            BreakerState::Closed { .. } => {}
+            BreakerState::Open { until } if until <= Instant::now() => {
+                log_at!(logging.token_requests(), "Sending probe request through open circuit breaker");
+                *state = BreakerState::HalfOpen;
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => {
+                return Err(GitHubAuthenticatorError::CircuitOpen);
+            }
+        }
+
+        Ok(Probe { breaker: self, logging, finished: false })
+    }
+
+    fn open(&self, state: &mut BreakerState, logging: &LoggingPolicy) {
+        log_at!(logging.request_failures(), cool_down = ?self.cool_down, "Opening circuit breaker");
+        *state = BreakerState::Open { until: Instant::now() + self.cool_down };
+    }
+}
+
+// Tracks an admitted request so that the breaker does not remain half open if a probe request is
+// dropped before it completes
+struct Probe<'a> {
+    breaker: &'a CircuitBreaker,
+    logging: &'a LoggingPolicy,
+    finished: bool,
+}
+
+impl Probe<'_> {
+    fn finish(&mut self, failed: bool) {
+        self.finished = true;
+
+        let mut state = self.breaker.state.lock().unwrap();
+
+        match (*state, failed) {
+            (BreakerState::Closed { failures }, true) if failures + 1 >= self.breaker.failure_threshold => {
+                self.breaker.open(&mut state, self.logging);
+            }
+            (BreakerState::Closed { failures }, true) => {
+                *state = BreakerState::Closed { failures: failures + 1 };
+            }
+            (BreakerState::HalfOpen, true) => self.breaker.open(&mut state, self.logging),
+            (BreakerState::HalfOpen, false) => {
+                log_at!(self.logging.token_requests(), "Closing circuit breaker");
+                *state = BreakerState::Closed { failures: 0 };
+            }
+            (_, false) => *state = BreakerState::Closed { failures: 0 },
+            // The breaker was opened by a concurrent request
+            (BreakerState::Open { .. }, true) => {}
+        }
+    }
+}
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let mut state = self.breaker.state.lock().unwrap();
+
+            // Let the next request probe instead
+            if *state == BreakerState::HalfOpen {
+                *state = BreakerState::Open { until: Instant::now() };
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, TimeDelta};
+use http::{HeaderMap, HeaderValue};
+use jsonwebtoken::EncodingKey;
+use std::{fmt::{Debug, Display}, sync::Arc};
+
+use crate::{app::MAX_JWT_DURATION, GitHubAppAuthenticator, GitHubAuthenticatorError, GitHubHost, HttpTransport, JwtConfig, KeySource, TokenRequest};
+
+/// A builder for a [`GitHubAppAuthenticator`] that validates the configuration as a whole. Unlike
+/// the mutators of [`GitHubAppAuthenticator`], the order in which settings are configured does not
+/// matter: a base uri always takes precedence over the endpoint derived from the host.
+///
+/// ```no_run
+/// # use github_app_authenticator::{GitHubAppAuthenticator, GitHubAuthenticatorError, KeySource};
+/// # fn example() -> Result<(), GitHubAuthenticatorError> {
+/// let app = GitHubAppAuthenticator::builder()
+///     .with_app_id(12345)
+///     .with_key_source(KeySource::File("/etc/github/app.pem".into()))
+///     .with_user_agent("example-service")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct GitHubAppAuthenticatorBuilder {
+    app_id: Option<u32>,
+    key: Option<Key>,
+    // The user agent, or a description of why the supplied user agent is invalid
+    user_agent: Option<Result<HeaderValue, String>>,
+    api_version: Option<Result<HeaderValue, String>>,
+    host: GitHubHost,
+    base_uri: Option<String>,
+    default_headers: HeaderMap,
+    transport: Option<Arc<dyn HttpTransport>>,
+    timeout: Option<std::time::Duration>,
+    jwt_duration: Option<TimeDelta>,
+    jwt_config: JwtConfig,
+    default_request: TokenRequest,
+    client_id: Option<String>,
+}
+
+#[derive(Clone)]
+enum Key {
+    Pem(Vec<u8>),
+    Source(KeySource),
+}
+
+impl Debug for GitHubAppAuthenticatorBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHubAppAuthenticatorBuilder")
+            .field("app_id", &self.app_id)
+            .field("host", &self.host)
+            .field("base_uri", &self.base_uri)
+            .finish()
+    }
+}
+
+impl GitHubAppAuthenticator {
+    /// Start building an app authenticator, see [`GitHubAppAuthenticatorBuilder`].
+    pub fn builder() -> GitHubAppAuthenticatorBuilder {
+        GitHubAppAuthenticatorBuilder::default()
+    }
+}
+
+impl GitHubAppAuthenticatorBuilder {
+    /// Configure the id of the app. Required.
+    pub fn with_app_id(mut self, app_id: u32) -> Self {
+        self.app_id = Some(app_id);
+        self
+    }
+
+    /// Configure the private key of the app in PEM format. Either this or
+    /// [`Self::with_key_source`] is required.
+    pub fn with_key(mut self, key: Vec<u8>) -> Self {
+        self.key = Some(Key::Pem(key));
+        self
+    }
+
+    /// Configure where to read the private key of the app from. The key is read by
+    /// [`Self::build`].
+    pub fn with_key_source(mut self, source: KeySource) -> Self {
+        self.key = Some(Key::Source(source));
+        self
+    }
+
+    /// Configure the user agent to send with all requests, either as a [`HeaderValue`] or as a
+    /// string that is validated by [`Self::build`]. Defaults to
+    /// `github-app-authenticator/<version> (app-id <id>)`.
+    pub fn with_user_agent<T>(mut self, user_agent: T) -> Self where T: TryInto<HeaderValue>, T::Error: Display {
+        self.user_agent = Some(user_agent.try_into().map_err(|err| err.to_string()));
+        self
+    }
+
+    /// Configure the version of the REST API to request, see
+    /// [`GitHubAppAuthenticator::with_api_version`].
+    pub fn with_api_version<T>(mut self, version: T) -> Self where T: TryInto<HeaderValue>, T::Error: Display {
+        self.api_version = Some(version.try_into().map_err(|err| err.to_string()));
+        self
+    }
+
+    /// Configure the GitHub deployment that the app is registered on. Defaults to github.com.
+    pub fn with_host(mut self, host: GitHubHost) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Override the base uri of the API that is derived from the host.
+    pub fn with_base_uri<T>(mut self, base_uri: T) -> Self where T: ToString {
+        self.base_uri = Some(base_uri.to_string());
+        self
+    }
+
+    /// Configure headers to send with every request, see
+    /// [`GitHubAppAuthenticator::with_default_headers`].
+    pub fn with_default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Configure the transport to send requests via, see
+    /// [`GitHubAppAuthenticator::with_transport`].
+    pub fn with_transport<T>(mut self, transport: T) -> Self where T: HttpTransport + 'static {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Configure the client to send requests via, see [`GitHubAppAuthenticator::with_client`].
+    #[cfg(feature = "reqwest")]
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        self.with_transport(crate::ReqwestTransport::new(client))
+    }
+
+    /// Configure how long to wait for any single request to complete, see
+    /// [`GitHubAppAuthenticator::with_timeout`].
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Configure how long the JWTs that authenticate requests to GitHub App endpoints are valid
+    /// for. Must be positive and at most 10 minutes. Defaults to 60 seconds.
+    pub fn with_jwt_duration(mut self, duration: TimeDelta) -> Self {
+        self.jwt_duration = Some(duration);
+        self
+    }
+
+    /// Configure a `kid` header and additional claims to add to JWTs, see
+    /// [`GitHubAppAuthenticator::with_jwt_config`].
+    pub fn with_jwt_config(mut self, config: JwtConfig) -> Self {
+        self.jwt_config = config;
+        self
+    }
+
+    /// Configure the token request that installation authenticators use by default, see
+    /// [`GitHubAppAuthenticator::default_request`].
+    pub fn with_default_request(mut self, request: TokenRequest) -> Self {
+        self.default_request = request;
+        self
+    }
+
+    /// Configure the client id of the app, see [`GitHubAppAuthenticator::with_client_id`].
+    pub fn with_client_id<T>(mut self, client_id: T) -> Self where T: ToString {
+        self.client_id = Some(client_id.to_string());
+        self
+    }
+
+    /// Validate the configuration and create the authenticator. Fails if a required setting is
+    /// missing, if the key can not be read or parsed, if the user agent or API version is not a
+    /// valid header value, or if the JWT duration is not positive or exceeds 10 minutes.
+    pub fn build(self) -> Result<GitHubAppAuthenticator, GitHubAuthenticatorError> {
+        let app_id = self
+            .app_id
+            .ok_or_else(|| GitHubAuthenticatorError::InvalidConfiguration("an app id is required".to_string()))?;
+        let key = match self.key {
+            Some(Key::Pem(key)) => key,
+            Some(Key::Source(source)) => source.load()?,
+            None => return Err(GitHubAuthenticatorError::InvalidConfiguration("a private key is required".to_string())),
+        };
+        let user_agent = match self.user_agent {
+            Some(Ok(user_agent)) => user_agent,
+            Some(Err(err)) => return Err(GitHubAuthenticatorError::InvalidConfiguration(format!("invalid user agent: {}", err))),
+            None => default_user_agent(app_id),
+        };
+        let api_version = self
+            .api_version
+            .transpose()
+            .map_err(|err| GitHubAuthenticatorError::InvalidConfiguration(format!("invalid API version: {}", err)))?;
+
+        // Catch unusable keys now rather than on the first request
+        EncodingKey::from_rsa_pem(&key).map_err(|_| GitHubAuthenticatorError::FailedToParseKey)?;
+
+        if let Some(duration) = self.jwt_duration {
+            if duration > MAX_JWT_DURATION {
+                return Err(GitHubAuthenticatorError::JwtDurationTooLong(duration));
+            }
+
+            if duration <= time::zero() {
+                return Err(GitHubAuthenticatorError::InvalidConfiguration(format!(
+                    "the JWT duration must be positive, got {}",
+                    duration
+                )));
+            }
+        }
+
+        let mut app = GitHubAppAuthenticator::new(app_id, key, user_agent);
+        app.with_host(self.host);
+        app.with_default_request(self.default_request);
+
+        if let Some(client_id) = self.client_id {
+            app.with_client_id(client_id);
+        }
+        app.with_default_headers(self.default_headers);
+        app.with_jwt_config(self.jwt_config);
+
+        if let Some(base_uri) = self.base_uri {
+            app.with_base_uri(base_uri);
+        }
+
+        if let Some(transport) = self.transport {
+            app.with_shared_transport(transport);
+        }
+
+        if let Some(timeout) = self.timeout {
+            app.with_timeout(timeout);
+        }
+
+        if let Some(duration) = self.jwt_duration {
+            app.with_jwt_duration(duration);
+        }
+
+        if let Some(version) = api_version {
+            app.with_api_version(version);
+        }
+
+        Ok(app)
+    }
+}
+
+// Identifies the crate and the app, as GitHub requires a user agent on every request
+fn default_user_agent(app_id: u32) -> HeaderValue {
+    let user_agent = format!("github-app-authenticator/{} (app-id {})", env!("CARGO_PKG_VERSION"), app_id);
+
+    // Consists of visible ASCII characters only
+    HeaderValue::try_from(user_agent).expect("default user agent is a valid header value")
+}
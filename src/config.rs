@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use serde::{Deserialize, Deserializer};
+use std::{fmt::Debug, path::PathBuf};
+
+use crate::{GitHubAppAuthenticator, GitHubAuthenticatorError, GitHubHost, TokenRequest};
+
+/// The configuration of an app authenticator, for declaring it in the configuration file of a
+/// service. For instance in TOML:
+///
+/// ```toml
+/// app_id = 12345
+/// key = { file = "/etc/github/app.pem" }
+/// host = "https://github.example.com"
+/// user_agent = "example-service"
+///
+/// [token_request.permissions]
+/// contents = "read"
+/// ```
+///
+/// See [`GitHubAppAuthenticator::from_config`].
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthenticatorConfig {
+    pub app_id: u32,
+    /// The client id of the app, which is only needed for acting on behalf of users, see
+    /// [`GitHubAppAuthenticator::oauth_client`].
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Where to read the private key of the app from.
+    pub key: KeySource,
+    /// The GitHub deployment that the app is registered on: `github.com`, the domain of a ghe.com
+    /// enterprise such as `octocorp.ghe.com`, or the URL of a GitHub Enterprise Server instance.
+    /// Defaults to `github.com`.
+    #[serde(default, deserialize_with = "deserialize_host")]
+    pub host: GitHubHost,
+    /// Overrides the base uri of the API that is derived from the host.
+    #[serde(default)]
+    pub base_uri: Option<String>,
+    /// The user agent to send with all requests. Defaults to
+    /// `github-app-authenticator/<version> (app-id <id>)`.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// The token request to use for installation authenticators by default, see
+    /// [`GitHubAppAuthenticator::default_request`].
+    #[serde(default)]
+    pub token_request: TokenRequest,
+}
+
+/// The source of a private key in PEM format.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// The key itself.
+    Inline(String),
+    /// A file that contains the key.
+    File(PathBuf),
+    /// An environment variable that contains the key.
+    Env(String),
+}
+
+impl Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Inline(_) => f.debug_tuple("Inline").field(&"<redacted>").finish(),
+            KeySource::File(path) => f.debug_tuple("File").field(path).finish(),
+            KeySource::Env(name) => f.debug_tuple("Env").field(name).finish(),
+        }
+    }
+}
+
+impl KeySource {
+    /// Read the key from its source.
+    pub fn load(&self) -> Result<Vec<u8>, GitHubAuthenticatorError> {
+        match self {
+            KeySource::Inline(key) => Ok(key.as_bytes().to_vec()),
+            KeySource::File(path) => std::fs::read(path).map_err(|err| {
+                GitHubAuthenticatorError::FailedToLoadKey(format!("failed to read {}: {}", path.display(), err))
+            }),
+            KeySource::Env(name) => std::env::var(name).map(String::into_bytes).map_err(|err| {
+                GitHubAuthenticatorError::FailedToLoadKey(format!("failed to read ${}: {}", name, err))
+            }),
+        }
+    }
+}
+
+fn deserialize_host<'de, D>(deserializer: D) -> Result<GitHubHost, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+}
+
+impl GitHubAppAuthenticator {
+    /// Create an app authenticator from its configuration, reading the private key from its
+    /// source.
+    pub fn from_config(config: &AuthenticatorConfig) -> Result<Self, GitHubAuthenticatorError> {
+        let mut builder = GitHubAppAuthenticator::builder()
+            .with_app_id(config.app_id)
+            .with_key_source(config.key.clone())
+            .with_host(config.host.clone())
+            .with_default_request(config.token_request.clone());
+
+        if let Some(client_id) = &config.client_id {
+            builder = builder.with_client_id(client_id);
+        }
+
+        if let Some(user_agent) = &config.user_agent {
+            builder = builder.with_user_agent(user_agent.as_str());
+        }
+
+        if let Some(base_uri) = &config.base_uri {
+            builder = builder.with_base_uri(base_uri);
+        }
+
+        builder.build()
+    }
+}
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use serde::Serialize;
+use std::{collections::BTreeMap, io::{Read, Write}, sync::{Arc, Mutex}};
+
+use crate::{GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator};
+
+static USERNAME: &str = "x-access-token";
+
+/// A backend for Docker's credential helper protocol that answers requests for the container
+/// registry of the app's GitHub host with installation access tokens.
+///
+/// A binary named `docker-credential-<name>` that calls [`DockerCredentialHelper::run`] with its
+/// first argument can be configured via `credHelpers` in Docker's `config.json`. Failures must be
+/// reported by exiting with a non-zero status.
+#[derive(Clone)]
+pub struct DockerCredentialHelper {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+    // The token that was handed out last. Docker does not report the rejected secret when erasing
+    // credentials, so this is the token that is discarded on erase
+    issued: Arc<Mutex<Option<String>>>,
+}
+
+impl std::fmt::Debug for DockerCredentialHelper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DockerCredentialHelper")
+            .field("authenticator", &self.authenticator)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Credentials<'a> {
+    #[serde(rename = "ServerURL")]
+    server_url: &'a str,
+    username: &'a str,
+    secret: &'a str,
+}
+
+impl DockerCredentialHelper {
+    /// Create a helper that answers requests with tokens from the given authenticator.
+    pub fn new(authenticator: RefreshingGitHubInstallationAuthenticator) -> Self {
+        Self { authenticator, issued: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Handle a single `get`, `store`, `erase`, or `list` operation, reading the request from stdin
+    /// and writing the response to stdout.
+    pub async fn run(&self, operation: &str) -> Result<(), GitHubAuthenticatorError> {
+        self.handle(operation, std::io::stdin().lock(), std::io::stdout().lock()).await
+    }
+
+    /// Handle a single operation, reading the request from `input` and writing the response to
+    /// `output`. Requests for other registries fail with
+    /// [`GitHubAuthenticatorError::CredentialsNotFound`] after writing the error message that
+    /// Docker expects.
+    pub async fn handle<R, W>(&self, operation: &str, mut input: R, mut output: W) -> Result<(), GitHubAuthenticatorError>
+    where
+        R: Read,
+        W: Write,
+    {
+        let registry = self.authenticator.host().container_registry();
+
+        let response = match operation {
+            "get" => {
+                let server_url = read_to_string(&mut input)?;
+
+                if normalize(&server_url) != registry {
+                    let _ = write!(output, "{}", GitHubAuthenticatorError::CredentialsNotFound);
+                    return Err(GitHubAuthenticatorError::CredentialsNotFound);
+                }
+
+                let token = self.authenticator.access_token().await?;
+                let response = serde_json::to_string(&Credentials {
+                    server_url: server_url.trim(),
+                    username: USERNAME,
+                    secret: &token,
+                })
+                .map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+                *self.issued.lock().unwrap() = Some(token);
+                response
+            }
+            "erase" => {
+                // Only the token that was handed out is discarded, as it may have been replaced
+                // since
+                if normalize(&read_to_string(&mut input)?) == registry {
+                    if let Some(token) = self.issued.lock().unwrap().take() {
+                        self.authenticator.invalidate_if(&token);
+                    }
+                }
+                return Ok(());
+            }
+            "list" => serde_json::to_string(&BTreeMap::from([(registry.as_str(), USERNAME)]))
+                .map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?,
+            // Credentials are minted on demand, so there is nothing to store
+            _ => return Ok(()),
+        };
+
+        write!(output, "{}", response)
+            .and_then(|_| output.flush())
+            .map_err(GitHubAuthenticatorError::CredentialHelperFailed)
+    }
+}
+
+fn read_to_string<R>(input: &mut R) -> Result<String, GitHubAuthenticatorError>
+where
+    R: Read,
+{
+    let mut value = String::new();
+    input
+        .read_to_string(&mut value)
+        .map_err(GitHubAuthenticatorError::CredentialHelperFailed)?;
+    Ok(value)
+}
+
+// Docker may identify a registry by its host or by a url
+fn normalize(server_url: &str) -> &str {
+    let server_url = server_url.trim();
+    let server_url = server_url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(server_url);
+
+    server_url.split('/').next().unwrap_or(server_url)
+}
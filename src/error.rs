@@ -4,23 +4,405 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use http::StatusCode;
+use crate::time::{self, Timestamp, TimeDelta};
+use http::{header::{DATE, RETRY_AFTER}, Response, StatusCode};
+#[cfg(feature = "reqwest")]
 use reqwest::Error as ClientError;
-use std::num::ParseIntError;
+use serde::Deserialize;
+use std::{fmt::Display, num::ParseIntError};
 use thiserror::Error;
 
+use crate::permissions::Permissions;
+
 #[derive(Debug, Error)]
 pub enum GitHubAuthenticatorError {
+    #[cfg(feature = "reqwest")]
     #[error("Failed to send request {0}")]
     Client(#[from] ClientError),
-    #[error("Failed to decode access token from GitHub")]
-    FailedToDecodeAccessTokenResponse,
+    #[error("Failed to send request {0}")]
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Credential helper failed to communicate with its caller {0}")]
+    CredentialHelperFailed(std::io::Error),
+    #[error("Failed to write token file {0}")]
+    FailedToWriteTokenFile(std::io::Error),
+    #[error("Failed to spawn process {0}")]
+    FailedToSpawnProcess(std::io::Error),
+    #[error("Failed to access token store {0}")]
+    FailedToAccessTokenStore(Box<dyn std::error::Error + Send + Sync>),
+    #[error("credentials not found in native keychain")]
+    CredentialsNotFound,
+    #[error("Failed to start runtime {0}")]
+    FailedToStartRuntime(std::io::Error),
+    #[error("Failed to encode request {0}")]
+    FailedToEncodeRequest(serde_json::Error),
+    #[error("Failed to create header {0}")]
+    FailedToCreateHeader(http::header::InvalidHeaderValue),
+    #[error("Failed to decode access token from GitHub {source}: {body}")]
+    FailedToDecodeAccessTokenResponse {
+        #[source]
+        source: serde_json::Error,
+        /// The start of the body that failed to decode, with credentials redacted.
+        body: String,
+    },
+    #[error("Failed to decode app response from GitHub {source}: {body}")]
+    FailedToDecodeAppResponse {
+        #[source]
+        source: serde_json::Error,
+        /// The start of the body that failed to decode, with credentials redacted.
+        body: String,
+    },
+    #[error("Failed to parse access token expiry {0:?}")]
+    FailedToParseTokenExpiry(String),
     #[error(transparent)]
     FailedToGenerateJwt(jsonwebtoken::errors::Error),
+    #[error("JWT duration of {}s exceeds GitHub's maximum of 10 minutes", time::whole_seconds(*.0))]
+    JwtDurationTooLong(TimeDelta),
     #[error("Failed to parse private key")]
     FailedToParseKey,
+    #[error("Failed to load private key: {0}")]
+    FailedToLoadKey(String),
+    #[error("Invalid authenticator configuration: {0}")]
+    InvalidConfiguration(String),
     #[error(transparent)]
     FailedToParseEnvValue(#[from] ParseIntError),
+    #[error("Webhook delivery is missing the {0} header")]
+    MissingWebhookHeader(&'static str),
+    #[error("Webhook signature does not match the payload")]
+    InvalidWebhookSignature,
+    #[error("Failed to decode webhook payload {0}")]
+    FailedToDecodeWebhook(serde_json::Error),
+    #[error("Invalid GitHub host: {0}")]
+    InvalidHost(String),
+    #[error("Invalid repository name {0}. Expected owner/repo")]
+    InvalidRepositoryName(String),
+    #[error("Invalid permissions: {0}")]
+    InvalidPermissions(String),
+    #[error("App is not installed on {0}")]
+    NotInstalled(String),
+    #[error("App request failed {0}")]
+    AppRequestFailed(Box<RequestFailure>),
+    #[error("Installation {0} is suspended")]
+    InstallationSuspended(u32),
+    #[error("Installation {0} no longer exists")]
+    InstallationGone(u32),
+    #[error("Installation {0} was not found")]
+    InstallationNotFound(u32),
+    #[error("OAuth request failed {0}")]
+    OAuthRequestFailed(String),
+    #[error("User must authorize the app again")]
+    ReauthorizationRequired,
     #[error("Installation token request failed {0}")]
-    InstallationRequestFailed(StatusCode),
-}
\ No newline at end of file
+    InstallationRequestFailed(Box<RequestFailure>),
+    #[error("GitHub rejected the app's credentials, check the private key, the app id and the local clock {0}")]
+    Unauthorized(Box<RequestFailure>),
+    #[error("Installation has not been granted the requested permissions {}: {failure}", .rejected.join(", "))]
+    PermissionsNotGranted {
+        /// The names of the permissions that GitHub rejected. GitHub does not always name them, in
+        /// which case every requested permission is listed.
+        rejected: Vec<String>,
+        failure: Box<RequestFailure>,
+    },
+    #[error("Rate limited by GitHub {failure}")]
+    RateLimited {
+        /// How long GitHub asked to wait before sending the request again, if it said.
+        retry_after: Option<std::time::Duration>,
+        failure: Box<RequestFailure>,
+    },
+    #[error("Installation token revocation failed {0}")]
+    RevocationFailed(Box<RequestFailure>),
+    #[error("Installation token validation failed {0}")]
+    TokenValidationFailed(Box<RequestFailure>),
+    #[error("Circuit breaker is open after repeated failures to reach GitHub")]
+    CircuitOpen,
+}
+
+// The number of characters of a body that failed to decode to keep in the error
+const BODY_SNIPPET_LENGTH: usize = 256;
+
+// Fields of GitHub responses whose values are credentials
+const SECRET_FIELDS: &[&str] = &["token", "access_token", "refresh_token", "client_secret", "webhook_secret", "pem"];
+
+// Shorten a body that failed to decode for inclusion in an error. Bodies are typically either JSON
+// of an unexpected shape, which may well contain credentials that are redacted, or error pages of
+// proxies in front of GitHub
+fn body_snippet(body: &[u8]) -> String {
+    let body = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+
+    let mut snippet = body
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(BODY_SNIPPET_LENGTH)
+        .collect::<String>();
+
+    if body.chars().nth(BODY_SNIPPET_LENGTH).is_some() {
+        snippet.push_str("...");
+    }
+
+    snippet
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&name.as_str()) {
+                    *value = serde_json::Value::String("***".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// The details of a request that GitHub responded to with an error status.
+#[derive(Clone, Debug)]
+pub struct RequestFailure {
+    pub status: StatusCode,
+    /// The explanation that GitHub gave for the failure, e.g. `Resource not accessible by
+    /// integration`.
+    pub message: Option<String>,
+    /// Details about the individual problems with the request, such as invalid repositories.
+    pub errors: Vec<RequestFailureDetail>,
+    /// A link to the documentation of the endpoint.
+    pub documentation_url: Option<String>,
+    /// The id that GitHub assigned to the request, from the `X-GitHub-Request-Id` header. GitHub
+    /// support asks for this id when investigating failed requests.
+    pub request_id: Option<String>,
+    /// The time at which GitHub responded, from the `Date` header.
+    pub date: Option<Timestamp>,
+    /// How long GitHub asked to wait before sending the request again, from the `Retry-After`
+    /// header or, once the rate limit has been exhausted, the `X-RateLimit-Reset` header.
+    pub retry_after: Option<std::time::Duration>,
+}
+
+/// A single problem with a failed request. GitHub reports some problems as plain messages, in
+/// which case only the message is set.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct RequestFailureDetail {
+    pub resource: Option<String>,
+    pub field: Option<String>,
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    #[serde(default)]
+    errors: Vec<ErrorDetail>,
+    documentation_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Detail(RequestFailureDetail),
+    Message(String),
+}
+
+impl RequestFailure {
+    // Bodies that are not JSON, for instance those of proxies in front of GitHub, only contribute
+    // their status
+    pub(crate) fn from_response(response: &Response<Vec<u8>>) -> Self {
+        let body = serde_json::from_slice::<ErrorBody>(response.body()).unwrap_or_default();
+
+        let errors = body
+            .errors
+            .into_iter()
+            .map(|error| match error {
+                ErrorDetail::Detail(detail) => detail,
+                ErrorDetail::Message(message) => RequestFailureDetail {
+                    message: Some(message),
+                    ..Default::default()
+                },
+            })
+            .collect();
+
+        let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok());
+
+        let date = header(DATE.as_str())
+            .and_then(time::parse_rfc2822);
+
+        // The reset time is relative to GitHub's clock, which the Date header tells us about
+        let rate_limit_reset = || {
+            let reset = header("x-ratelimit-reset")?.parse::<i64>().ok()?;
+            let now = time::unix_seconds(date.unwrap_or_else(time::now));
+            Some(std::time::Duration::from_secs(reset.saturating_sub(now).max(0) as u64))
+        };
+
+        let retry_after = header(RETRY_AFTER.as_str())
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .map(std::time::Duration::from_secs)
+            .or_else(|| {
+                (header("x-ratelimit-remaining") == Some("0"))
+                    .then(rate_limit_reset)
+                    .flatten()
+            });
+
+        Self {
+            status: response.status(),
+            message: body.message,
+            errors,
+            documentation_url: body.documentation_url,
+            request_id: header("x-github-request-id").map(str::to_string),
+            date,
+            retry_after,
+        }
+    }
+
+    /// Check whether GitHub may accept the request if it is sent again later. See
+    /// [`GitHubAuthenticatorError::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        match self.status {
+            StatusCode::TOO_MANY_REQUESTS => true,
+            // Rate limited requests are rejected with a 403 that is only distinguished from
+            // authorization failures by its headers and message
+            StatusCode::FORBIDDEN => {
+                self.retry_after.is_some()
+                    || self
+                        .message
+                        .as_ref()
+                        .map(|message| message.to_lowercase().contains("rate limit"))
+                        .unwrap_or(false)
+            }
+            status => status.is_server_error(),
+        }
+    }
+}
+
+impl GitHubAuthenticatorError {
+    pub(crate) fn access_token_decode(source: serde_json::Error, body: &[u8]) -> Self {
+        Self::FailedToDecodeAccessTokenResponse { source, body: body_snippet(body) }
+    }
+
+    pub(crate) fn app_response_decode(source: serde_json::Error, body: &[u8]) -> Self {
+        Self::FailedToDecodeAppResponse { source, body: body_snippet(body) }
+    }
+
+    // Classify a failed installation token request by how callers need to handle it. Suspended
+    // and missing installations can only be reported if the installation is known
+    pub(crate) fn token_request_failed(installation_id: Option<u32>, requested: Option<&Permissions>, failure: RequestFailure) -> Self {
+        // GitHub only distinguishes some failures from others with the same status by the message
+        // that it responds with
+        let message = failure.message.as_deref().unwrap_or_default().to_lowercase();
+
+        match (failure.status, installation_id) {
+            (StatusCode::UNAUTHORIZED, _) => Self::Unauthorized(Box::new(failure)),
+            (StatusCode::FORBIDDEN, Some(installation_id)) if message.contains("suspended") => Self::InstallationSuspended(installation_id),
+            (StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS, _) if failure.is_retryable() => Self::RateLimited {
+                retry_after: failure.retry_after,
+                failure: Box::new(failure),
+            },
+            (StatusCode::NOT_FOUND, Some(installation_id)) => Self::InstallationNotFound(installation_id),
+            (StatusCode::UNPROCESSABLE_ENTITY, _) if message.contains("permission") => Self::PermissionsNotGranted {
+                rejected: rejected_permissions(&failure, requested),
+                failure: Box::new(failure),
+            },
+            _ => Self::InstallationRequestFailed(Box::new(failure)),
+        }
+    }
+
+    /// The details of the response if GitHub responded to the request with an error status.
+    pub fn request_failure(&self) -> Option<&RequestFailure> {
+        match self {
+            GitHubAuthenticatorError::PermissionsNotGranted { failure, .. }
+            | GitHubAuthenticatorError::RateLimited { failure, .. } => Some(failure.as_ref()),
+            GitHubAuthenticatorError::AppRequestFailed(failure)
+            | GitHubAuthenticatorError::InstallationRequestFailed(failure)
+            | GitHubAuthenticatorError::Unauthorized(failure)
+            | GitHubAuthenticatorError::RevocationFailed(failure)
+            | GitHubAuthenticatorError::TokenValidationFailed(failure) => Some(failure.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// The id that GitHub assigned to the failed request, if GitHub responded to it.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_failure()?.request_id.as_deref()
+    }
+
+    /// The status that GitHub responded with, if GitHub responded to the request.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            GitHubAuthenticatorError::InstallationSuspended(_) => Some(StatusCode::FORBIDDEN),
+            GitHubAuthenticatorError::InstallationGone(_) | GitHubAuthenticatorError::InstallationNotFound(_) => Some(StatusCode::NOT_FOUND),
+            _ => self.request_failure().map(|failure| failure.status),
+        }
+    }
+
+    /// How long GitHub asked to wait before sending the request again, if it rate limited the
+    /// request.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        self.request_failure()?.retry_after
+    }
+
+    /// Check whether the request that failed may succeed if it is sent again later. Failures to
+    /// reach GitHub, server errors and rate limiting are retryable. Failures that GitHub will
+    /// keep responding with, such as rejected credentials, missing installations or invalid
+    /// requests, are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(all(feature = "reqwest", not(target_arch = "wasm32")))]
+            GitHubAuthenticatorError::Client(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            // The fetch API that backs reqwest on wasm does not distinguish connection failures
+            #[cfg(all(feature = "reqwest", target_arch = "wasm32"))]
+            GitHubAuthenticatorError::Client(err) => err.is_timeout() || err.is_request() || err.is_body(),
+            GitHubAuthenticatorError::Transport(_) | GitHubAuthenticatorError::CircuitOpen => true,
+            _ => self.request_failure().map(RequestFailure::is_retryable).unwrap_or(false),
+        }
+    }
+}
+
+// The permissions named by the details of a failure, or every requested permission if there are
+// no details
+fn rejected_permissions(failure: &RequestFailure, requested: Option<&Permissions>) -> Vec<String> {
+    let named = failure
+        .errors
+        .iter()
+        .filter_map(|error| error.field.as_deref())
+        .map(|field| field.trim_start_matches("permissions.").to_string())
+        .collect::<Vec<_>>();
+
+    if !named.is_empty() {
+        return named;
+    }
+
+    requested
+        .map(|requested| {
+            requested
+                .mismatches(&Permissions::default())
+                .into_iter()
+                .map(|mismatch| mismatch.permission.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl Display for RequestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.status)?;
+
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+
+        for error in &self.errors {
+            if let Some(message) = &error.message {
+                write!(f, " ({})", message)?;
+            } else if let (Some(field), Some(code)) = (&error.field, &error.code) {
+                write!(f, " ({} {})", field, code)?;
+            }
+        }
+
+        Ok(())
+    }
+}
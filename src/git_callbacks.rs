@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use tokio::runtime::Handle;
+
+use crate::RefreshingGitHubInstallationAuthenticator;
+
+// libgit2 asks for credentials again whenever the remote rejects them. Give up after a fresh token
+// has been rejected instead of looping forever
+static MAX_ATTEMPTS: usize = 3;
+
+/// Supplies installation access tokens to libgit2 operations, for instance clones and fetches via
+/// [`git2::RemoteCallbacks::credentials`].
+///
+/// libgit2 calls into the credentials callback synchronously, so tokens are fetched by blocking on
+/// the given runtime. Operations must therefore run outside of the runtime's async context, for
+/// instance via [`tokio::task::spawn_blocking`].
+#[derive(Clone, Debug)]
+pub struct GitCredentials {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+    runtime: Handle,
+}
+
+impl GitCredentials {
+    /// Create credentials backed by the given authenticator that fetch tokens on `runtime`.
+    pub fn new(authenticator: RefreshingGitHubInstallationAuthenticator, runtime: Handle) -> Self {
+        Self { authenticator, runtime }
+    }
+
+    /// Create callbacks for a single git operation. Credentials that the remote rejects, for
+    /// instance because the token expired during a long running operation, are replaced with a
+    /// newly fetched token.
+    pub fn remote_callbacks(&self) -> RemoteCallbacks<'static> {
+        let credentials = self.clone();
+        let mut attempts = 0;
+        let mut rejected: Option<String> = None;
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, _username, allowed| {
+            attempts += 1;
+
+            if attempts > MAX_ATTEMPTS {
+                return Err(git2::Error::from_str("GitHub rejected the installation access token"));
+            }
+
+            // Being asked again means that the token handed out last was rejected. Only that token
+            // is discarded, as another clone of the authenticator may have already replaced it
+            if let Some(token) = rejected.take() {
+                credentials.authenticator.invalidate_if(&token);
+            }
+
+            let token = credentials.token(url, allowed)?;
+            let cred = Cred::userpass_plaintext("x-access-token", &token);
+            rejected = Some(token);
+
+            cred
+        });
+
+        callbacks
+    }
+
+    /// Create credentials for the given remote url, fetching a new token if the current token has
+    /// expired. Only https remotes of the app's GitHub host are supported.
+    pub fn credentials(&self, url: &str, allowed: CredentialType) -> Result<Cred, git2::Error> {
+        Cred::userpass_plaintext("x-access-token", &self.token(url, allowed)?)
+    }
+
+    // Get the token to authenticate to the given remote url with
+    fn token(&self, url: &str, allowed: CredentialType) -> Result<String, git2::Error> {
+        let endpoint = self.authenticator.host().web_endpoint();
+
+        if !url.starts_with(&format!("{}/", endpoint)) {
+            return Err(git2::Error::from_str(&format!("{} is not hosted on {}", url, endpoint)));
+        }
+
+        if !allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Err(git2::Error::from_str("The remote does not accept username and password credentials"));
+        }
+
+        self.runtime
+            .block_on(self.authenticator.access_token())
+            .map_err(|err| git2::Error::from_str(&err.to_string()))
+    }
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::io::{BufRead, Write};
+
+use crate::{GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator};
+
+/// A backend for git's credential helper protocol that answers requests for the app's GitHub host
+/// with installation access tokens. Requests for any other host are left unanswered so that git
+/// can fall back to other helpers.
+///
+/// A binary that calls [`GitCredentialHelper::run`] with its first argument can be configured as
+/// `credential.helper`.
+#[derive(Clone, Debug)]
+pub struct GitCredentialHelper {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+}
+
+impl GitCredentialHelper {
+    /// Create a helper that answers requests with tokens from the given authenticator.
+    pub fn new(authenticator: RefreshingGitHubInstallationAuthenticator) -> Self {
+        Self { authenticator }
+    }
+
+    /// Handle a single `get`, `store`, or `erase` operation, reading the request from stdin and
+    /// writing the response to stdout.
+    pub async fn run(&self, operation: &str) -> Result<(), GitHubAuthenticatorError> {
+        self.handle(operation, std::io::stdin().lock(), std::io::stdout().lock()).await
+    }
+
+    /// Handle a single operation, reading the request from `input` and writing the response to
+    /// `output`. Unknown operations are ignored, as required by the protocol.
+    pub async fn handle<R, W>(&self, operation: &str, input: R, mut output: W) -> Result<(), GitHubAuthenticatorError>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let request = CredentialRequest::read(input)?;
+
+        let endpoint = self.authenticator.host().web_endpoint();
+        if !request.is_for(&endpoint) {
+            return Ok(());
+        }
+
+        match operation {
+            "get" => {
+                let token = self.authenticator.access_token().await?;
+                write!(output, "username=x-access-token\npassword={}\n", token)
+                    .and_then(|_| output.flush())
+                    .map_err(GitHubAuthenticatorError::CredentialHelperFailed)?;
+            }
+            // git erases credentials that the remote rejected, so the token must not be reused.
+            // Only the erased token is discarded, as it may have been replaced since
+            "erase" => {
+                if let Some(password) = &request.password {
+                    self.authenticator.invalidate_if(password);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct CredentialRequest {
+    protocol: Option<String>,
+    host: Option<String>,
+    password: Option<String>,
+}
+
+impl CredentialRequest {
+    // Read attributes up until the first blank line or the end of input
+    fn read<R>(input: R) -> Result<Self, GitHubAuthenticatorError>
+    where
+        R: BufRead,
+    {
+        let mut request = Self::default();
+
+        for line in input.lines() {
+            let line = line.map_err(GitHubAuthenticatorError::CredentialHelperFailed)?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            match line.split_once('=') {
+                Some(("protocol", value)) => request.protocol = Some(value.to_string()),
+                Some(("host", value)) => request.host = Some(value.to_string()),
+                Some(("password", value)) => request.password = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(request)
+    }
+
+    // Check whether the request is for the given endpoint, which includes the protocol, host, and
+    // optionally port
+    fn is_for(&self, endpoint: &str) -> bool {
+        match (&self.protocol, &self.host) {
+            (Some(protocol), Some(host)) => endpoint == format!("{}://{}", protocol, host),
+            _ => false,
+        }
+    }
+}
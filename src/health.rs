@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time;
+use http::{Method, StatusCode};
+use std::time::Duration;
+
+use crate::{GitHubAppAuthenticator, RequestFailure};
+
+/// The outcome of [`GitHubAppAuthenticator::health_check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Whether GitHub responded to the request.
+    pub reachable: bool,
+    /// Whether GitHub accepted the JWT of the app.
+    pub authenticated: bool,
+    /// The status that GitHub responded with, if it responded.
+    pub status: Option<StatusCode>,
+    /// The time taken to sign the JWT and receive the response.
+    pub latency: Duration,
+    /// The number of requests remaining before the app is rate limited, if GitHub reported it.
+    pub rate_limit_remaining: Option<u32>,
+    /// A description of the failure, if the check failed.
+    pub error: Option<String>,
+}
+
+impl HealthReport {
+    /// Check whether GitHub is reachable and accepts the credentials of the app.
+    pub fn is_healthy(&self) -> bool {
+        self.reachable && self.authenticated
+    }
+}
+
+impl GitHubAppAuthenticator {
+    /// Check that GitHub is reachable and accepts the credentials of the app by fetching the app
+    /// with a newly generated JWT. Failures are reported as part of the result rather than as an
+    /// error, which makes for a simple readiness probe.
+    pub async fn health_check(&self) -> HealthReport {
+        let started = time::now();
+        let result = self.send_as_app(Method::GET, "/app", None).await;
+        let latency = time::to_std(time::between(time::now(), started));
+
+        match result {
+            Ok(response) => {
+                let status = response.status();
+                let rate_limit = self.record_rate_limit(response.headers());
+                let authenticated = status == StatusCode::OK;
+
+                HealthReport {
+                    reachable: true,
+                    authenticated,
+                    status: Some(status),
+                    latency,
+                    rate_limit_remaining: rate_limit.map(|rate_limit| rate_limit.remaining),
+                    error: (!authenticated).then(|| RequestFailure::from_response(&response).to_string()),
+                }
+            }
+            Err(err) => HealthReport {
+                reachable: false,
+                authenticated: false,
+                status: None,
+                latency,
+                rate_limit_remaining: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
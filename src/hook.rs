@@ -0,0 +1,166 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{Timestamp};
+use futures_core::Stream;
+use futures_util::TryStreamExt;
+use http::{Method, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Debug;
+
+use crate::{logging::log_at, GitHubAppAuthenticator, GitHubAuthenticatorError, RequestFailure};
+
+/// An attempt to deliver a webhook event to the app's webhook url.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HookDelivery {
+    pub id: u64,
+    /// The id shared by all attempts of delivering the same event. Sent as the
+    /// `X-GitHub-Delivery` header.
+    pub guid: String,
+    pub delivered_at: Timestamp,
+    /// Whether the attempt was a redelivery of an earlier attempt.
+    pub redelivery: bool,
+    /// The time in seconds that the webhook url took to respond.
+    pub duration: f64,
+    /// A description of the outcome of the attempt, e.g. `OK`.
+    pub status: String,
+    /// The status code that the webhook url responded with, or 0 if it could not be reached.
+    pub status_code: u16,
+    pub event: String,
+    pub action: Option<String>,
+    pub installation_id: Option<u64>,
+    pub repository_id: Option<u64>,
+}
+
+/// The configuration of the app's webhook.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HookConfig {
+    pub url: Option<String>,
+    /// The format that payloads are delivered in, either `json` or `form`.
+    pub content_type: Option<String>,
+    /// A placeholder if a secret is configured. GitHub never returns the secret itself.
+    pub secret: Option<String>,
+    /// Whether deliveries skip verifying the certificate of the webhook url.
+    #[serde(default, deserialize_with = "deserialize_insecure_ssl")]
+    pub insecure_ssl: Option<bool>,
+}
+
+/// Changes to the configuration of the app's webhook. Fields that are not set are left unchanged.
+#[derive(Clone, Default, Serialize)]
+pub struct HookConfigUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The secret to sign deliveries with. See [`WebhookVerifier`](crate::webhooks::WebhookVerifier).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_insecure_ssl")]
+    pub insecure_ssl: Option<bool>,
+}
+
+impl Debug for HookConfigUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookConfigUpdate")
+            .field("url", &self.url)
+            .field("content_type", &self.content_type)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .field("insecure_ssl", &self.insecure_ssl)
+            .finish()
+    }
+}
+
+// GitHub reports `insecure_ssl` as either "0" and "1" or as the numbers 0 and 1
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InsecureSsl {
+    Number(u8),
+    String(String),
+}
+
+fn deserialize_insecure_ssl<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<InsecureSsl>::deserialize(deserializer)?.map(|value| match value {
+        InsecureSsl::Number(number) => number != 0,
+        InsecureSsl::String(string) => string != "0",
+    }))
+}
+
+fn serialize_insecure_ssl<S>(value: &Option<bool>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(true) => serializer.serialize_str("1"),
+        _ => serializer.serialize_str("0"),
+    }
+}
+
+impl GitHubAppAuthenticator {
+    /// Fetch the configuration of the app's webhook.
+    pub async fn hook_config(&self) -> Result<HookConfig, GitHubAuthenticatorError> {
+        self.get("/app/hook/config").await
+    }
+
+    /// Update the configuration of the app's webhook, for instance to rotate its secret. Returns
+    /// the configuration after the update.
+    pub async fn update_hook_config(&self, update: &HookConfigUpdate) -> Result<HookConfig, GitHubAuthenticatorError> {
+        let body = serde_json::to_vec(update).map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+        log_at!(self.logging().token_requests(), ?update, "Updating webhook configuration");
+
+        let response = self.send_as_app(Method::PATCH, "/app/hook/config", Some(body)).await?;
+
+        if response.status() == StatusCode::OK {
+            serde_json::from_slice(response.body()).map_err(|err| {
+                log_at!(self.logging().internal_errors(), ?err, "Failed to decode webhook configuration");
+                GitHubAuthenticatorError::app_response_decode(err, response.body())
+            })
+        } else {
+            let status = response.status();
+            let body = self.logging().body(response.body());
+
+            log_at!(self.logging().request_failures(), ?status, ?body, "Failed to update webhook configuration");
+
+            Err(GitHubAuthenticatorError::AppRequestFailed(Box::new(RequestFailure::from_response(&response))))
+        }
+    }
+
+    /// Fetch the recent delivery attempts of the app's webhook, most recent first. Deliveries are
+    /// requested 100 at a time.
+    pub async fn hook_deliveries(&self) -> Result<Vec<HookDelivery>, GitHubAuthenticatorError> {
+        self.hook_deliveries_stream(100).try_collect().await
+    }
+
+    /// Stream the recent delivery attempts of the app's webhook, most recent first, requesting
+    /// `page_size` deliveries at a time. GitHub allows for at most 100 deliveries per page.
+    pub fn hook_deliveries_stream(&self, page_size: u8) -> impl Stream<Item = Result<HookDelivery, GitHubAuthenticatorError>> + '_ {
+        self.paginate(format!("{}/app/hook/deliveries?per_page={}", self.base_endpoint(), page_size.clamp(1, 100)))
+    }
+
+    /// Ask GitHub to attempt a delivery again. The new attempt is made asynchronously and shows up
+    /// in [`Self::hook_deliveries`] with `redelivery` set.
+    pub async fn redeliver(&self, delivery_id: u64) -> Result<(), GitHubAuthenticatorError> {
+        let path = format!("/app/hook/deliveries/{}/attempts", delivery_id);
+
+        log_at!(self.logging().token_requests(), ?delivery_id, "Requesting webhook redelivery");
+
+        let response = self.send_as_app(Method::POST, &path, None).await?;
+
+        if response.status() == StatusCode::ACCEPTED {
+            Ok(())
+        } else {
+            let status = response.status();
+            let body = self.logging().body(response.body());
+
+            log_at!(self.logging().request_failures(), ?status, ?body, ?delivery_id, "Failed to request webhook redelivery");
+
+            Err(GitHubAuthenticatorError::AppRequestFailed(Box::new(RequestFailure::from_response(&response))))
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::str::FromStr;
+
+use crate::GitHubAuthenticatorError;
+
+/// The GitHub deployment that an app is registered on. Prefer the validating constructors
+/// [`GitHubHost::ghes`] and [`GitHubHost::ghe_com`] over constructing variants directly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum GitHubHost {
+    /// github.com
+    #[default]
+    Dotcom,
+    /// A GitHub Enterprise Server instance, identified by the URL of its web interface, for
+    /// instance `https://github.example.com`.
+    Ghes { base_url: String },
+    /// A GitHub Enterprise Cloud enterprise with data residency, identified by its subdomain of
+    /// ghe.com.
+    GhecDataResidency { subdomain: String },
+}
+
+impl GitHubHost {
+    /// Create a host for a GitHub Enterprise Server instance from the URL of its web interface,
+    /// for instance `https://github.example.com`. The URL must use https and must not have a
+    /// trailing slash.
+    pub fn ghes<T>(base_url: T) -> Result<Self, GitHubAuthenticatorError> where T: ToString {
+        let base_url = base_url.to_string();
+
+        if !base_url.starts_with("https://") {
+            return Err(GitHubAuthenticatorError::InvalidHost(format!("{} must use https", base_url)));
+        }
+
+        Self::ghes_allowing_http(base_url)
+    }
+
+    /// Create a host for a GitHub Enterprise Server instance that may be served over plain http.
+    /// This is only intended for testing against local mock servers.
+    pub fn ghes_allowing_http<T>(base_url: T) -> Result<Self, GitHubAuthenticatorError> where T: ToString {
+        let base_url = base_url.to_string();
+
+        let host = base_url
+            .strip_prefix("https://")
+            .or_else(|| base_url.strip_prefix("http://"))
+            .ok_or_else(|| GitHubAuthenticatorError::InvalidHost(format!("{} is not an http(s) url", base_url)))?;
+
+        if host.is_empty() {
+            Err(GitHubAuthenticatorError::InvalidHost(format!("{} is missing a host", base_url)))
+        } else if base_url.ends_with('/') {
+            Err(GitHubAuthenticatorError::InvalidHost(format!("{} must not have a trailing slash", base_url)))
+        } else {
+            Ok(GitHubHost::Ghes { base_url })
+        }
+    }
+
+    /// Create a host for a GitHub Enterprise Cloud enterprise with data residency from its
+    /// subdomain of ghe.com. The enterprise hosted at `https://octocorp.ghe.com` has the subdomain
+    /// `octocorp`.
+    pub fn ghe_com<T>(subdomain: T) -> Result<Self, GitHubAuthenticatorError> where T: ToString {
+        let subdomain = subdomain.to_string();
+
+        let valid = !subdomain.is_empty()
+            && !subdomain.starts_with('-')
+            && !subdomain.ends_with('-')
+            && subdomain.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+        if valid {
+            Ok(GitHubHost::GhecDataResidency { subdomain })
+        } else {
+            Err(GitHubAuthenticatorError::InvalidHost(format!("{} is not a valid ghe.com subdomain", subdomain)))
+        }
+    }
+
+    /// The base endpoint of the REST API.
+    pub fn api_endpoint(&self) -> String {
+        match self {
+            GitHubHost::Dotcom => "https://api.github.com".to_string(),
+            GitHubHost::Ghes { base_url } => format!("{}/api/v3", base_url.trim_end_matches('/')),
+            GitHubHost::GhecDataResidency { subdomain } => format!("https://api.{}.ghe.com", subdomain),
+        }
+    }
+
+    /// The base endpoint of the web interface. This is also the host that repositories are cloned
+    /// from.
+    pub fn web_endpoint(&self) -> String {
+        match self {
+            GitHubHost::Dotcom => "https://github.com".to_string(),
+            GitHubHost::Ghes { base_url } => base_url.trim_end_matches('/').to_string(),
+            GitHubHost::GhecDataResidency { subdomain } => format!("https://{}.ghe.com", subdomain),
+        }
+    }
+
+    /// The host of the container registry, for instance `ghcr.io`.
+    pub fn container_registry(&self) -> String {
+        match self {
+            GitHubHost::Dotcom => "ghcr.io".to_string(),
+            GitHubHost::Ghes { base_url } => {
+                let (_, host) = base_url.split_once("://").unwrap_or(("https", base_url));
+                format!("containers.{}", host.trim_end_matches('/'))
+            }
+            GitHubHost::GhecDataResidency { subdomain } => format!("containers.{}.ghe.com", subdomain),
+        }
+    }
+}
+
+/// Parses a host from `github.com`, the domain of a ghe.com enterprise such as `octocorp.ghe.com`,
+/// or the URL of a GitHub Enterprise Server instance such as `https://github.example.com`.
+impl FromStr for GitHubHost {
+    type Err = GitHubAuthenticatorError;
+
+    fn from_str(host: &str) -> Result<Self, Self::Err> {
+        if host == "github.com" {
+            Ok(GitHubHost::Dotcom)
+        } else if let Some(subdomain) = host.strip_suffix(".ghe.com") {
+            GitHubHost::ghe_com(subdomain)
+        } else {
+            GitHubHost::ghes(host.trim_end_matches('/'))
+        }
+    }
+}
@@ -4,118 +4,815 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use chrono::{DateTime, Duration, Utc};
-use http::{header::USER_AGENT, StatusCode};
-use reqwest::Client;
-use serde::Deserialize;
-use std::{fmt::Debug, sync::{Arc, RwLock}};
+use crate::time::{self, Timestamp, TimeDelta};
+use http::{Method, StatusCode};
+use serde::{Deserialize, Deserializer};
+use std::{collections::HashMap, fmt::Debug, sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock}};
+use tokio::sync::Mutex;
+use tracing::Instrument;
 
-use crate::{GitHubAppAuthenticator, TokenRequest, GitHubAuthenticatorError, GitHubInstallationToken};
+use crate::logging::log_at;
 
+use crate::{permissions::{PermissionMismatch, Permissions}, token::{decode_access_token, deserialize_granted_permissions, undecodable_permissions}, token_fingerprint, AccessToken, AuditEvent, Account, GitHubAppAuthenticator, GitHubHost, TokenRequest, GitHubAuthenticatorError, GitHubInstallationToken, RepositorySelection, RequestFailure};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{store::SharedStore, RetryDecision, TokenStore};
+
+/// An installation of a GitHub App on a user or organization account.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Installation {
+    pub id: u32,
+    /// The account that the app is installed on.
+    pub account: Option<Account>,
+    /// Either `User` or `Organization`.
+    pub target_type: String,
+    pub target_id: u64,
+    pub app_id: u32,
+    /// The permissions granted to the installation. Individual permissions are omitted if GitHub
+    /// reports them at a level that this crate does not understand.
+    #[serde(default, deserialize_with = "deserialize_granted_permissions")]
+    pub permissions: Option<Permissions>,
+    /// The webhook events that the installation receives.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Whether the app has access to all repositories of the account or only a selection.
+    pub repository_selection: Option<RepositorySelection>,
+    /// The time at which the installation was suspended, if it is suspended. Suspended
+    /// installations can not be issued access tokens.
+    #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
+    pub suspended_at: Option<Timestamp>,
+    pub suspended_by: Option<Account>,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub created_at: Timestamp,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub updated_at: Timestamp,
+}
+
+// Webhook payloads report some installation timestamps as seconds since the epoch rather than as
+// RFC 3339 strings
 #[derive(Deserialize)]
-pub(crate) struct GitHubInstallationTokenResponse {
-    pub token: String,
-    pub expires_at: DateTime<Utc>,
+#[serde(untagged)]
+enum RawTimestamp {
+    Seconds(i64),
+    DateTime(Timestamp),
+}
+
+impl RawTimestamp {
+    fn into_timestamp<E>(self) -> Result<Timestamp, E>
+    where
+        E: serde::de::Error,
+    {
+        match self {
+            RawTimestamp::Seconds(seconds) => time::from_unix_seconds(seconds)
+                .ok_or_else(|| E::custom(format!("timestamp {} is out of range", seconds))),
+            RawTimestamp::DateTime(date_time) => Ok(date_time),
+        }
+    }
+}
+
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Timestamp, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    RawTimestamp::deserialize(deserializer)?.into_timestamp()
+}
+
+fn deserialize_optional_timestamp<'de, D>(deserializer: D) -> Result<Option<Timestamp>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<RawTimestamp>::deserialize(deserializer)?
+        .map(RawTimestamp::into_timestamp)
+        .transpose()
+}
+
+impl Installation {
+    /// Check whether the installation is suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
 }
 
 /// An authenticator for fetching access tokens for a given GitHub App installation
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct GitHubInstallationAuthenticator {
     app: GitHubAppAuthenticator,
-    inner: Client,
-    installation_api_endpoint: String,
+    installation_id: u32,
+    // Unexpired tokens by the request they were issued for, if caching is enabled
+    token_cache: Option<Arc<RwLock<TokenCache>>>,
+}
+
+#[derive(Debug, Default)]
+struct TokenCache {
+    tokens: HashMap<TokenRequest, GitHubInstallationToken>,
+    hits: u64,
+    misses: u64,
+    refreshes: u64,
+    evictions: u64,
+}
+
+#[derive(Deserialize)]
+struct InstallationPermissions {
+    permissions: Permissions,
 }
 
 impl GitHubInstallationAuthenticator {
     pub(crate) fn new(app: GitHubAppAuthenticator, installation_id: u32) -> Self {
-        let endpoint = format!("{}/app/installations/{}/access_tokens", app.base_endpoint(), installation_id);
         GitHubInstallationAuthenticator {
             app,
-            inner: Client::new(),
-            installation_api_endpoint: endpoint
+            installation_id,
+            token_cache: None,
         }
     }
 
+    /// The id of the app that this authenticator authenticates as.
+    pub fn app_id(&self) -> u32 {
+        self.app.app_id()
+    }
+
+    /// The id of the installation that this authenticator fetches tokens for.
+    pub fn installation_id(&self) -> u32 {
+        self.installation_id
+    }
+
+    /// Cache tokens by the request they were issued for, so that repeated calls to
+    /// [`Self::access_token`] with identical requests return the same token until it is about to
+    /// expire. Clones of this authenticator share the cache. Unlike
+    /// [`RefreshingGitHubInstallationAuthenticator`], concurrent calls for the same request may
+    /// still each fetch a token.
+    pub fn with_token_cache(mut self) -> Self {
+        self.token_cache = Some(Arc::new(RwLock::new(TokenCache::default())));
+        self
+    }
+
+    /// Discard all cached tokens without revoking them.
+    pub fn clear_token_cache(&self) {
+        if let Some(cache) = &self.token_cache {
+            cache.write().unwrap().tokens.clear();
+        }
+    }
+
+    /// Counters of the token cache shared by this authenticator and its clones, if caching is
+    /// enabled. Expired tokens count as evicted once they are dropped from the cache.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.token_cache.as_ref().map(|cache| {
+            let cache = cache.read().unwrap();
+
+            CacheStats {
+                hits: cache.hits,
+                misses: cache.misses,
+                refreshes: cache.refreshes,
+                evictions: cache.evictions,
+                size: cache.tokens.len(),
+            }
+        })
+    }
+
     /// Upgrade this authenticator into an authenticator that keeps a token alive.
     pub fn into_refreshing(self, request: TokenRequest) -> RefreshingGitHubInstallationAuthenticator {
         RefreshingGitHubInstallationAuthenticator::new(self, request)
     }
 
-    /// Fetch a new access token for a given request on this installation
+    /// Upgrade this authenticator into an authenticator that keeps a token alive for the default
+    /// token request of the app, see [`GitHubAppAuthenticator::default_request`].
+    pub fn into_refreshing_default(self) -> RefreshingGitHubInstallationAuthenticator {
+        let request = self.app.default_request().clone();
+        self.into_refreshing(request)
+    }
+
+    /// Fetch a new access token for a given request on this installation, or a cached token if
+    /// caching is enabled. See [`Self::with_token_cache`].
     pub async fn access_token(&self, request: &TokenRequest) -> Result<String, GitHubAuthenticatorError> {
-        Ok(self.request_token(request).await?.token)
+        Ok(self.cached_request_token(request).await?.token)
+    }
+
+    /// Fetch a new access token for a given request on this installation along with the
+    /// permissions and repositories that GitHub granted it
+    pub async fn access_token_detailed(&self, request: &TokenRequest) -> Result<AccessToken, GitHubAuthenticatorError> {
+        self.cached_request_token(request).await
+    }
+
+    /// Fetch an access token with the given permissions that is scoped to the given repositories
+    /// of this installation, given by name without the owner prefix, along with the permissions
+    /// and repositories that GitHub granted it. This is a shorthand for
+    /// [`Self::access_token_detailed`] with the corresponding request.
+    ///
+    /// ```no_run
+    /// # use github_app_authenticator::{GitHubAuthenticatorError, GitHubInstallationAuthenticator, permissions::Permissions};
+    /// # async fn example(authenticator: GitHubInstallationAuthenticator) -> Result<(), GitHubAuthenticatorError> {
+    /// let token = authenticator.token_for(&["repo-a"], Permissions::contents_read_only()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn token_for<R>(&self, repositories: &[R], permissions: Permissions) -> Result<AccessToken, GitHubAuthenticatorError> where R: AsRef<str> {
+        let request = TokenRequest {
+            permissions: Some(permissions),
+            repositories: Some(repositories.iter().map(|repository| repository.as_ref().to_string()).collect()),
+            ..Default::default()
+        };
+
+        self.access_token_detailed(&request).await
+    }
+
+    /// Check the permissions of a request against the permissions that have been granted to the
+    /// installation. Any requested permission that the installation has not been granted, or that
+    /// has been granted at a lower level, is reported as a mismatch. GitHub rejects token requests
+    /// that have any mismatches.
+    pub async fn validate_request(&self, request: &TokenRequest) -> Result<Vec<PermissionMismatch>, GitHubAuthenticatorError> {
+        match &request.permissions {
+            Some(requested) => {
+                let installation: InstallationPermissions = self
+                    .app
+                    .get(&format!("/app/installations/{}", self.installation_id))
+                    .await?;
+
+                Ok(requested.mismatches(&installation.permissions))
+            }
+            // Requests without permissions receive all of the permissions of the installation
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Revoke an access token so that GitHub no longer accepts it. Any token issued for this
+    /// installation may be revoked, regardless of how it was requested.
+    pub async fn revoke(&self, token: &str) -> Result<(), GitHubAuthenticatorError> {
+        let endpoint = format!("{}/installation/token", self.app.base_endpoint());
+
+        let logging = self.app.logging();
+        log_at!(logging.token_requests(), url = %logging.id(&endpoint), "Revoking installation access token");
+
+        let response = self.app.send(Method::DELETE, &endpoint, Some(token), None).await?;
+
+        if response.status() == StatusCode::NO_CONTENT {
+            self.app.audit(AuditEvent::TokenRevoked {
+                app_id: self.app.app_id(),
+                installation_id: self.installation_id,
+                fingerprint: token_fingerprint(token),
+                revoked_at: time::now(),
+            });
+
+            Ok(())
+        } else {
+            let status = response.status();
+            let body = logging.body(response.body());
+
+            log_at!(logging.request_failures(), ?status, ?body, "Failed to revoke installation access token");
+
+            Err(GitHubAuthenticatorError::RevocationFailed(Box::new(RequestFailure::from_response(&response))))
+        }
+    }
+
+    /// Check whether GitHub still accepts an access token. A token that has expired or been
+    /// revoked is reported as invalid.
+    pub async fn is_valid(&self, token: &str) -> Result<bool, GitHubAuthenticatorError> {
+        let endpoint = format!("{}/installation/repositories?per_page=1", self.app.base_endpoint());
+
+        let response = self.app.send(Method::GET, &endpoint, Some(token), None).await?;
+
+        match response.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::UNAUTHORIZED => Ok(false),
+            status => {
+                let logging = self.app.logging();
+                let body = logging.body(response.body());
+
+                log_at!(logging.request_failures(), ?status, ?body, "Failed to check installation access token");
+
+                Err(GitHubAuthenticatorError::TokenValidationFailed(Box::new(RequestFailure::from_response(&response))))
+            }
+        }
+    }
+
+    async fn cached_request_token(&self, request: &TokenRequest) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let cache = match &self.token_cache {
+            Some(cache) => cache,
+            None => return self.request_token(request).await,
+        };
+
+        let cached = {
+            let mut cache = cache.write().unwrap();
+            let cached = cache
+                .tokens
+                .get(request)
+                .filter(|token| token.expires_at > time::now())
+                .map(|token| token.access_token.clone());
+
+            match cached {
+                Some(_) => cache.hits += 1,
+                None => cache.misses += 1,
+            }
+
+            cached
+        };
+
+        if let Some(token) = cached {
+            return Ok(token);
+        }
+
+        let token = self.request_token(request).await?;
+
+        let mut cache = cache.write().unwrap();
+        let now = time::now();
+        let size = cache.tokens.len();
+        cache.tokens.retain(|_, token| token.expires_at > now);
+        cache.evictions += (size - cache.tokens.len()) as u64;
+        cache.refreshes += 1;
+        cache.tokens.insert(request.clone(), GitHubInstallationToken::from(token.clone()));
+
+        Ok(token)
     }
 
     async fn request_token(
         &self,
         request: &TokenRequest,
-    ) -> Result<GitHubInstallationTokenResponse, GitHubAuthenticatorError> {
-        tracing::info!(?request, url = ?self.installation_api_endpoint, "Requesting installation access token");
+    ) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let logging = self.app.logging();
+        let span = tracing::info_span!(
+            "github.installation_token.mint",
+            app_id = %logging.id(self.app.app_id()),
+            installation_id = %logging.id(self.installation_id),
+            otel.status_code = tracing::field::Empty,
+        );
+        let result = self.request_token_with_retries(request).instrument(span.clone()).await;
+
+        if result.is_err() {
+            span.record("otel.status_code", "ERROR");
+        }
+
+        result
+    }
+
+    async fn request_token_with_retries(
+        &self,
+        request: &TokenRequest,
+    ) -> Result<AccessToken, GitHubAuthenticatorError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(policy) = self.app.retry_policy() {
+            let start = std::time::Instant::now();
+            let mut attempt = 1;
+
+            loop {
+                let err = match self.attempt_token(request).await {
+                    Err(err) => err,
+                    result => return result,
+                };
+
+                match policy.retry(attempt, &err, start.elapsed()) {
+                    RetryDecision::Retry(delay) => {
+                        let logging = self.app.logging();
+                        log_at!(logging.token_requests(), err = %logging.id(&err), ?attempt, ?delay, "Retrying installation access token request");
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    RetryDecision::DoNotRetry => return Err(err),
+                }
+            }
+        }
+
+        self.attempt_token(request).await
+    }
+
+    async fn attempt_token(
+        &self,
+        request: &TokenRequest,
+    ) -> Result<AccessToken, GitHubAuthenticatorError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(breaker) = self.app.circuit_breaker() {
+            return breaker.call(self.app.logging(), self.mint_token(request)).await;
+        }
+
+        self.mint_token(request).await
+    }
+
+    async fn mint_token(
+        &self,
+        request: &TokenRequest,
+    ) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let result = self.send_token_request(request).await;
+        crate::metrics::token_request(self.app.app_id(), self.installation_id, &result);
+
+        result
+    }
+
+    async fn send_token_request(
+        &self,
+        request: &TokenRequest,
+    ) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let logging = self.app.logging();
+        let endpoint = crate::protocol::token_endpoint(&self.app.base_endpoint(), self.installation_id);
+        log_at!(logging.token_requests(), ?request, url = %logging.id(&endpoint), "Requesting installation access token");
 
-        let jwt = self.app.generate_jwt(Duration::seconds(60))?;
+        let body = serde_json::to_vec(request).map_err(|err| {
+            log_at!(logging.internal_errors(), ?err, "Failed to encode installation access token request");
+            GitHubAuthenticatorError::FailedToEncodeRequest(err)
+        })?;
 
         let response = self
-            .inner
-            .post(&self.installation_api_endpoint)
-            .bearer_auth(jwt)
-            .header(USER_AGENT, self.app.user_agent())
-            .json(request)
-            .send()
+            .app
+            .send_with_jwt(Method::POST, &endpoint, Some(body))
             .await?;
 
+        let rate_limit = self.app.record_rate_limit(response.headers());
+
         if response.status() == StatusCode::CREATED {
-            let body = response.text().await?;
-            let token: GitHubInstallationTokenResponse =
-                serde_json::from_str(&body).map_err(|err| {
-                    tracing::error!(
-                        ?err,
-                        "Failed to decode installation access token response body"
-                    );
-                    GitHubAuthenticatorError::FailedToDecodeAccessTokenResponse
-                })?;
+            let mut token = decode_access_token(response.body()).map_err(|err| {
+                log_at!(
+                    logging.internal_errors(),
+                    ?err,
+                    "Failed to decode installation access token response body"
+                );
+                err
+            })?;
+            token.rate_limit = rate_limit;
+
+            for err in undecodable_permissions(response.body()) {
+                log_at!(logging.internal_errors(), ?err, "Failed to decode granted access token permission");
+            }
+
+            self.app.audit(AuditEvent::TokenIssued {
+                app_id: self.app.app_id(),
+                installation_id: self.installation_id,
+                request: request.clone(),
+                fingerprint: token_fingerprint(&token.token),
+                issued_at: time::now(),
+                expires_at: token.expires_at,
+            });
 
             Ok(token)
         } else {
             let status = response.status();
-            let body = response.text().await?;
+            let body = logging.body(response.body());
+            let failure = RequestFailure::from_response(&response);
 
-            tracing::info!(?status, ?body, "Failed to request installation access token");
+            log_at!(logging.request_failures(), ?status, ?body, request_id = ?failure.request_id, "Failed to request installation access token");
 
-            Err(GitHubAuthenticatorError::InstallationRequestFailed(status))
+            Err(GitHubAuthenticatorError::token_request_failed(Some(self.installation_id), request.permissions.as_ref(), failure))
         }
     }
 }
 
 /// An authenticator for continually fetching an access token for a given GitHub App installation
-/// and permissions request pair. 
-#[derive(Debug)]
+/// and permissions request pair. Cloning is cheap, and all clones share the same cached token.
+#[derive(Clone, Debug)]
 pub struct RefreshingGitHubInstallationAuthenticator {
-    authenticator: GitHubInstallationAuthenticator,
-    request: TokenRequest,
+    authenticator: Arc<GitHubInstallationAuthenticator>,
+    request: Arc<TokenRequest>,
     token: Arc<RwLock<Option<GitHubInstallationToken>>>,
+    refresh_lock: Arc<Mutex<()>>,
+    gone: Arc<AtomicBool>,
+    stats: Arc<RwLock<TokenStats>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    store: Option<SharedStore>,
+}
+
+// Puts a token that is being revoked back in place unless the revocation succeeds. The token is
+// still valid if revoking it failed or was cancelled, and can continue to be used
+struct PendingRevocation<'a> {
+    slot: &'a RwLock<Option<GitHubInstallationToken>>,
+    token: Option<GitHubInstallationToken>,
+}
+
+impl Drop for PendingRevocation<'_> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            *self.slot.write().unwrap() = Some(token);
+        }
+    }
+}
+
+/// A snapshot of the token bookkeeping of a [`RefreshingGitHubInstallationAuthenticator`], shared
+/// by all of its clones.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenStats {
+    /// The number of tokens fetched.
+    pub tokens_minted: u64,
+    /// The number of failed attempts to fetch a token.
+    pub failures: u64,
+    /// The time at which the most recent token was fetched.
+    pub last_refresh: Option<Timestamp>,
+    /// A description of the most recent failure to fetch a token, if any. The failure is kept
+    /// after tokens have been fetched successfully again.
+    pub last_error: Option<String>,
+    /// The time at which the most recent failure occurred.
+    pub last_error_at: Option<Timestamp>,
+    /// The time at which the current token expires, if a token has been fetched.
+    pub expires_at: Option<Timestamp>,
+    /// The number of requests for a token that were served by the current token.
+    pub cache_hits: u64,
+    /// The number of requests for a token that required fetching a new token.
+    pub cache_misses: u64,
+}
+
+/// Counters of a token cache, for verifying that the cache reduces the number of tokens fetched
+/// from GitHub.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of requests for a token that were served from the cache.
+    pub hits: u64,
+    /// The number of requests for a token that were not served from the cache.
+    pub misses: u64,
+    /// The number of tokens fetched to fill the cache.
+    pub refreshes: u64,
+    /// The number of entries dropped from the cache, either because they expired or to make room
+    /// for other entries.
+    pub evictions: u64,
+    /// The number of entries currently in the cache.
+    pub size: usize,
 }
 
 impl RefreshingGitHubInstallationAuthenticator {
     fn new(authenticator: GitHubInstallationAuthenticator, request: TokenRequest) -> Self {
         Self {
-            authenticator,
-            request,
+            authenticator: Arc::new(authenticator),
+            request: Arc::new(request),
             token: Arc::new(RwLock::new(None)),
+            refresh_lock: Arc::new(Mutex::new(())),
+            gone: Arc::new(AtomicBool::new(false)),
+            stats: Arc::new(RwLock::new(TokenStats::default())),
+            #[cfg(not(target_arch = "wasm32"))]
+            store: None,
         }
     }
 
-    fn token_expired(&self) -> bool {
-        let token = self.token.read().unwrap();
-        token.is_none() || token.as_ref().unwrap().expires_at <= Utc::now()
+    /// The id of the app that this authenticator authenticates as.
+    pub fn app_id(&self) -> u32 {
+        self.authenticator.app_id()
+    }
+
+    /// The id of the installation that this authenticator fetches tokens for.
+    pub fn installation_id(&self) -> u32 {
+        self.authenticator.installation_id
+    }
+
+    /// Share tokens through `store`, for instance with the other replicas of a service. Before
+    /// fetching a token from GitHub, the store is checked for a token that is still valid. If none
+    /// is found, the replica that acquires the lease of the store fetches the token and puts it in
+    /// the store, while the other replicas wait for it to appear instead of fetching tokens of
+    /// their own. Failures of the store are logged, and fall back to fetching tokens directly.
+    ///
+    /// Tokens are stored under a key derived from the app, the installation and the token request.
+    /// [`Self::refresh`] always fetches a new token and replaces the stored token.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_token_store<S>(self, store: S) -> Self where S: TokenStore + 'static {
+        self.with_shared_token_store(Arc::new(store))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn with_shared_token_store(mut self, store: Arc<dyn TokenStore>) -> Self {
+        self.store = Some(SharedStore::new(
+            store,
+            self.authenticator.app.app_id(),
+            self.authenticator.installation_id,
+            &self.request,
+        ));
+        self
+    }
+
+    pub(crate) fn cached_token(&self, min_duration: TimeDelta) -> Option<AccessToken> {
+        if self.is_gone() {
+            return None;
+        }
+
+        self.token
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|token| token.expires_at > time::now() + min_duration)
+            .map(|token| token.access_token.clone())
     }
 
     /// Fetch an updated access token for the configured request.
     pub async fn access_token(&self) -> Result<String, GitHubAuthenticatorError> {
-        if self.token_expired() {
-            let token = GitHubInstallationToken::from(self.authenticator.request_token(&self.request).await?);
-            *self.token.write().unwrap() = Some(token);
+        Ok(self.access_token_detailed().await?.token)
+    }
+
+    /// Fetch an updated access token for the configured request along with the permissions and
+    /// repositories that GitHub granted it.
+    pub async fn access_token_detailed(&self) -> Result<AccessToken, GitHubAuthenticatorError> {
+        self.access_token_detailed_valid_for(time::zero()).await
+    }
+
+    /// Fetch an access token for the configured request that will remain valid for at least
+    /// `min_duration`. If the current token expires sooner, a new token is fetched early. GitHub
+    /// issues tokens that are valid for an hour, and cached tokens are considered expired 5
+    /// minutes before GitHub expires them, so durations beyond 55 minutes can not be satisfied
+    /// and result in a newly fetched token on every call.
+    pub async fn access_token_valid_for(&self, min_duration: TimeDelta) -> Result<String, GitHubAuthenticatorError> {
+        Ok(self.access_token_detailed_valid_for(min_duration).await?.token)
+    }
+
+    /// Fetch an access token for the configured request that will remain valid for at least
+    /// `min_duration` along with the permissions and repositories that GitHub granted it.
+    pub async fn access_token_detailed_valid_for(&self, min_duration: TimeDelta) -> Result<AccessToken, GitHubAuthenticatorError> {
+        if let Some(token) = self.cached_token(min_duration) {
+            self.record_cache_lookup(true);
+            return Ok(token);
+        }
+
+        // Only a single caller refreshes the token at a time. Any callers that were waiting on the
+        // lock pick up the token that was just stored instead of requesting another one
+        let _guard = self.refresh_lock.lock().await;
+
+        if let Some(token) = self.cached_token(min_duration) {
+            self.record_cache_lookup(true);
+            return Ok(token);
         }
 
-        Ok(self.token.read().unwrap().as_ref().unwrap().access_token.clone())
+        self.record_cache_lookup(false);
+        self.store_token(Some(min_duration)).await
+    }
+
+    fn record_cache_lookup(&self, hit: bool) {
+        let mut stats = self.stats.write().unwrap();
+        if hit {
+            stats.cache_hits += 1;
+        } else {
+            stats.cache_misses += 1;
+        }
+        drop(stats);
+
+        crate::metrics::token_cache(self.authenticator.app.app_id(), self.authenticator.installation_id, hit);
+    }
+
+    /// Fetch a new access token for the configured request regardless of whether the current
+    /// token has expired. The new token replaces the current token for all clones of this
+    /// authenticator.
+    pub async fn refresh(&self) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let _guard = self.refresh_lock.lock().await;
+        self.store_token(None).await
+    }
+
+    /// Discard the current token and fail all future token requests with
+    /// [`GitHubAuthenticatorError::InstallationGone`], for instance after the app has been
+    /// uninstalled. This affects all clones of this authenticator. Installations that GitHub
+    /// reports as not found are not marked as gone, as a misconfigured base uri or app id results
+    /// in the same response.
+    pub fn mark_gone(&self) {
+        self.gone.store(true, Ordering::SeqCst);
+        self.token.write().unwrap().take();
+    }
+
+    /// Check whether the installation has been marked as gone.
+    pub fn is_gone(&self) -> bool {
+        self.gone.load(Ordering::SeqCst)
+    }
+
+    /// The time at which GitHub will stop accepting the current token, if a token has been
+    /// fetched.
+    pub fn expires_at(&self) -> Option<Timestamp> {
+        self.token
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|token| token.access_token.expires_at)
+    }
+
+    /// The remaining lifetime of the current token, if a token has been fetched. A token that has
+    /// already expired has no remaining lifetime.
+    pub fn remaining(&self) -> Option<TimeDelta> {
+        self.expires_at()
+            .map(|expires_at| time::between(expires_at, time::now()).max(time::zero()))
+    }
+
+    /// A snapshot of the tokens fetched by this authenticator and its clones.
+    pub fn stats(&self) -> TokenStats {
+        TokenStats {
+            expires_at: self.expires_at(),
+            ..self.stats.read().unwrap().clone()
+        }
+    }
+
+    /// Revoke the current token, if a token has been fetched. The next request for a token will
+    /// fetch a new token.
+    pub async fn revoke(&self) -> Result<(), GitHubAuthenticatorError> {
+        let _guard = self.refresh_lock.lock().await;
+        self.revoke_current().await.map(|_| ())
+    }
+
+    // Callers must hold the refresh lock. Returns the token that was revoked, if any
+    async fn revoke_current(&self) -> Result<Option<String>, GitHubAuthenticatorError> {
+        let mut pending = PendingRevocation {
+            slot: &self.token,
+            token: self.token.write().unwrap().take(),
+        };
+
+        let current = pending.token.as_ref().map(|token| token.access_token.token.clone());
+
+        if let Some(token) = &current {
+            self.authenticator.revoke(token).await?;
+        }
+
+        pending.token = None;
+
+        Ok(current)
+    }
+
+    // Revoke the current token along with the token in the token store, removing the latter from
+    // the store. Returns whether any token was revoked. The refresh lock is held throughout, so
+    // that the token that is compared against the stored token is the one that was revoked
+    pub(crate) async fn revoke_everywhere(&self) -> Result<bool, GitHubAuthenticatorError> {
+        let _guard = self.refresh_lock.lock().await;
+        let current = self.revoke_current().await?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(store) = &self.store {
+            if let Some(stored) = store.take().await? {
+                // The stored token is the current token unless another replica fetched it
+                if current.as_deref() != Some(stored.token.as_str()) {
+                    self.authenticator.revoke(&stored.token).await?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(current.is_some())
+    }
+
+    /// Discard the current token without revoking it, for instance after GitHub has rejected it.
+    /// The next request for a token will fetch a new token.
+    pub fn invalidate(&self) {
+        self.token.write().unwrap().take();
+    }
+
+    /// Discard the current token without revoking it, but only if it is `rejected`. Use this
+    /// instead of [`Self::invalidate`] when GitHub rejects a token that was handed out earlier, so
+    /// that a token fetched since then is not discarded along with it. Returns whether the token
+    /// was discarded.
+    pub fn invalidate_if(&self, rejected: &str) -> bool {
+        let mut token = self.token.write().unwrap();
+
+        if token.as_ref().is_some_and(|token| token.access_token.token == rejected) {
+            token.take();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Get the GitHub deployment of the underlying app
+    pub(crate) fn host(&self) -> GitHubHost {
+        self.authenticator.app.host()
+    }
+
+    // Get the logging policy of the underlying app
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn logging(&self) -> &crate::LoggingPolicy {
+        self.authenticator.app.logging()
+    }
+
+    // Get the user agent of the underlying app
+    #[cfg(feature = "tower")]
+    pub(crate) fn user_agent(&self) -> http::HeaderValue {
+        self.authenticator.app.user_agent()
+    }
+
+    // Callers must hold the refresh lock. A token that remains valid for `min_duration` may be
+    // taken from the token store, while `None` always fetches a new token
+    async fn store_token(&self, min_duration: Option<TimeDelta>) -> Result<AccessToken, GitHubAuthenticatorError> {
+        if self.is_gone() {
+            return Err(GitHubAuthenticatorError::InstallationGone(self.authenticator.installation_id));
+        }
+
+        let started = time::now();
+        let result = self.fetch_token(min_duration).await;
+        crate::metrics::token_refresh(self.authenticator.app.app_id(), self.authenticator.installation_id, started);
+
+        let token = match result {
+            Ok(token) => {
+                let mut stats = self.stats.write().unwrap();
+                stats.tokens_minted += 1;
+                stats.last_refresh = Some(time::now());
+
+                GitHubInstallationToken::from(token)
+            }
+            Err(err) => {
+                let mut stats = self.stats.write().unwrap();
+                stats.failures += 1;
+                stats.last_error = Some(err.to_string());
+                stats.last_error_at = Some(time::now());
+
+                return Err(err);
+            }
+        };
+        let access_token = token.access_token.clone();
+        *self.token.write().unwrap() = Some(token);
+
+        Ok(access_token)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn fetch_token(&self, min_duration: Option<TimeDelta>) -> Result<AccessToken, GitHubAuthenticatorError> {
+        match &self.store {
+            Some(store) => {
+                store
+                    .fetch(min_duration, self.authenticator.app.logging(), || self.authenticator.request_token(&self.request))
+                    .await
+            }
+            None => self.authenticator.request_token(&self.request).await,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    async fn fetch_token(&self, _min_duration: Option<TimeDelta>) -> Result<AccessToken, GitHubAuthenticatorError> {
+        self.authenticator.request_token(&self.request).await
     }
 }
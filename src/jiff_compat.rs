@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use jiff::{SignedDuration, Timestamp};
+
+use crate::{AccessToken, Jwt};
+
+/// Convert a time reported by this crate into a [`Timestamp`]. Times beyond the range of
+/// [`Timestamp`], which GitHub never reports, are clamped to its bounds.
+pub fn to_timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp::new(time.timestamp(), time.timestamp_subsec_nanos() as i32).unwrap_or(if time.timestamp() < 0 {
+        Timestamp::MIN
+    } else {
+        Timestamp::MAX
+    })
+}
+
+/// Convert a [`Timestamp`] into a time that can be passed to this crate.
+pub fn from_timestamp(timestamp: Timestamp) -> DateTime<Utc> {
+    // The fractional second of timestamps before the epoch is negative in jiff, but positive in
+    // chrono. Every timestamp is within the range of chrono
+    let (seconds, nanos) = match timestamp.subsec_nanosecond() {
+        nanos if nanos < 0 => (timestamp.as_second() - 1, nanos + 1_000_000_000),
+        nanos => (timestamp.as_second(), nanos),
+    };
+
+    Utc.timestamp_opt(seconds, nanos as u32).single().unwrap_or_default()
+}
+
+/// Convert a duration used by this crate into a [`SignedDuration`].
+pub fn to_signed_duration(duration: Duration) -> SignedDuration {
+    SignedDuration::new(duration.num_seconds(), duration.subsec_nanos())
+}
+
+/// Convert a [`SignedDuration`] into a duration that can be passed to this crate, such as the
+/// minimum validity of [`crate::RefreshingGitHubInstallationAuthenticator::access_token_valid_for`].
+/// Durations beyond the range of chrono are clamped to its bounds.
+pub fn from_signed_duration(duration: SignedDuration) -> Duration {
+    let millis = duration.as_millis().clamp(-i128::from(i64::MAX), i128::from(i64::MAX)) as i64;
+    let millis = Duration::milliseconds(millis);
+
+    millis
+        .checked_add(&Duration::nanoseconds((duration.subsec_nanos() % 1_000_000).into()))
+        .unwrap_or(millis)
+}
+
+impl AccessToken {
+    /// The time at which GitHub will stop accepting the token, as a [`Timestamp`].
+    pub fn expires_at_timestamp(&self) -> Timestamp {
+        to_timestamp(self.expires_at)
+    }
+}
+
+impl Jwt {
+    /// The time at which the JWT was issued, according to the local clock, as a [`Timestamp`].
+    pub fn issued_at_timestamp(&self) -> Timestamp {
+        to_timestamp(self.issued_at())
+    }
+
+    /// The time at which GitHub will stop accepting the JWT, according to the local clock, as a
+    /// [`Timestamp`].
+    pub fn expires_at_timestamp(&self) -> Timestamp {
+        to_timestamp(self.expires_at())
+    }
+}
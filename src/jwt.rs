@@ -0,0 +1,147 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, Timestamp, TimeDelta};
+use http::HeaderValue;
+use serde_json::{Map, Value};
+use std::fmt::{Debug, Display};
+
+use crate::{token::bearer_authorization, GitHubAuthenticatorError};
+
+/// A JWT that authenticates requests to GitHub App endpoints, along with the times between which
+/// it is valid. Callers sending their own requests to app endpoints can hold on to a JWT and reuse
+/// it until it is about to expire. The JWT itself is omitted from debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Jwt {
+    token: String,
+    issued_at: Timestamp,
+    expires_at: Timestamp,
+}
+
+impl Jwt {
+    pub(crate) fn new(token: String, issued_at: Timestamp, expires_at: Timestamp) -> Self {
+        Self { token, issued_at, expires_at }
+    }
+
+    /// The encoded JWT.
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// The time at which the JWT was issued, according to the local clock.
+    pub fn issued_at(&self) -> Timestamp {
+        self.issued_at
+    }
+
+    /// The time at which GitHub will stop accepting the JWT, according to the local clock.
+    pub fn expires_at(&self) -> Timestamp {
+        self.expires_at
+    }
+
+    /// Check whether GitHub no longer accepts the JWT.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= time::now()
+    }
+
+    /// Check whether the JWT remains valid for at least `duration`, for instance to leave time for
+    /// a request to reach GitHub before reusing it.
+    pub fn is_valid_for(&self, duration: TimeDelta) -> bool {
+        self.expires_at > time::now() + duration
+    }
+
+    /// Create an Authorization header value that authenticates requests with this JWT. The value
+    /// is marked as sensitive so that it is omitted from debug output.
+    pub fn authorization_header(&self) -> Result<HeaderValue, GitHubAuthenticatorError> {
+        bearer_authorization(&self.token)
+    }
+}
+
+impl AsRef<str> for Jwt {
+    fn as_ref(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Display for Jwt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.token)
+    }
+}
+
+impl Debug for Jwt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jwt")
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl From<Jwt> for String {
+    fn from(jwt: Jwt) -> Self {
+        jwt.token
+    }
+}
+
+// Claims that GitHub requires and that are always set by the crate
+const RESERVED_CLAIMS: &[&str] = &["iat", "exp", "iss"];
+
+/// Additions to the JWTs that authenticate requests to GitHub App endpoints, for deployments where
+/// a proxy in front of GitHub Enterprise Server inspects the JWT. GitHub itself ignores both.
+///
+/// ```
+/// # use github_app_authenticator::JwtConfig;
+/// let config = JwtConfig::new()
+///     .with_kid("app-key-2023")
+///     .with_claim("aud", "github-gateway");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JwtConfig {
+    kid: Option<String>,
+    claims: Map<String, Value>,
+    // Reserved claims that were passed to `with_claim`, which are logged once the configuration is
+    // applied to an app
+    ignored_claims: Vec<String>,
+}
+
+impl JwtConfig {
+    /// Create a configuration that adds nothing to the JWT.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the `kid` header of the JWT, identifying the key that signed it.
+    pub fn with_kid<T>(mut self, kid: T) -> Self where T: ToString {
+        self.kid = Some(kid.to_string());
+        self
+    }
+
+    /// Add a claim to the JWT. The `iat`, `exp` and `iss` claims that GitHub requires are always
+    /// set by the crate, and attempts to set them are ignored.
+    pub fn with_claim<T>(mut self, name: &str, value: T) -> Self where T: Into<Value> {
+        if RESERVED_CLAIMS.contains(&name) {
+            self.ignored_claims.push(name.to_string());
+        } else {
+            self.claims.insert(name.to_string(), value.into());
+        }
+
+        self
+    }
+
+    /// Get the `kid` header of the JWT, if one is set.
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// Get the claims that are added to the JWT.
+    pub fn claims(&self) -> &Map<String, Value> {
+        &self.claims
+    }
+
+    pub(crate) fn ignored_claims(&self) -> &[String] {
+        &self.ignored_claims
+    }
+}
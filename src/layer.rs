@@ -0,0 +1,137 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use http::{header::{AUTHORIZATION, USER_AGENT}, HeaderValue, Request, Response, StatusCode};
+use http_body::Body;
+use std::{error::Error, future::Future, pin::Pin, task::{Context, Poll}};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{token::bearer_authorization, GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// A [`Layer`] that authenticates every request with an installation access token, replacing any
+/// existing Authorization header. A User-Agent header is added to requests that do not already
+/// have one. Requests without a body that are rejected with a 401 are retried once with a newly
+/// fetched token. Requests with a body can not be replayed, so their 401 is returned after the
+/// token has been invalidated, and the next request uses a new token.
+#[derive(Clone, Debug)]
+pub struct GitHubAuthLayer {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+}
+
+impl GitHubAuthLayer {
+    /// Create a layer that authenticates requests with tokens from the given authenticator.
+    pub fn new(authenticator: RefreshingGitHubInstallationAuthenticator) -> Self {
+        Self { authenticator }
+    }
+}
+
+impl<S> Layer<S> for GitHubAuthLayer {
+    type Service = GitHubAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GitHubAuthService {
+            inner,
+            authenticator: self.authenticator.clone(),
+        }
+    }
+}
+
+/// A service that authenticates requests before passing them to the inner service. See
+/// [`GitHubAuthLayer`].
+#[derive(Clone, Debug)]
+pub struct GitHubAuthService<S> {
+    inner: S,
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+}
+
+impl<S, B, R> Service<Request<B>> for GitHubAuthService<S>
+where
+    S: Service<Request<B>, Response = Response<R>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    B: Body + Default + Send + 'static,
+    R: Send,
+{
+    type Response = Response<R>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // Use the service that was driven to readiness and leave a fresh clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let authenticator = self.authenticator.clone();
+
+        Box::pin(async move {
+            // Bodies are streamed to the inner service, so only requests without a body can be
+            // replayed. Extensions can not be cloned and are therefore not carried over
+            let retry = request.body().is_end_stream().then(|| copy_request(&request));
+
+            let token = authenticator.access_token().await?;
+            let response = inner
+                .call(authorize(request, &token, authenticator.user_agent())?)
+                .await
+                .map_err(Into::into)?;
+
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+
+            // Only discard the token that was rejected, as other requests may have already
+            // replaced it
+            authenticator.invalidate_if(&token);
+
+            let retry = match retry {
+                Some(retry) => retry,
+                None => {
+                    tracing::info!("Installation token was rejected. Not retrying a request with a body");
+                    return Ok(response);
+                }
+            };
+
+            tracing::info!("Installation token was rejected. Retrying with a new token");
+
+            let token = authenticator.access_token().await?;
+            std::future::poll_fn(|cx| inner.poll_ready(cx))
+                .await
+                .map_err(Into::into)?;
+
+            inner
+                .call(authorize(retry, &token, authenticator.user_agent())?)
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
+
+fn copy_request<B>(request: &Request<B>) -> Request<B>
+where
+    B: Default,
+{
+    let mut copy = Request::new(B::default());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy
+}
+
+fn authorize<B>(mut request: Request<B>, token: &str, user_agent: HeaderValue) -> Result<Request<B>, GitHubAuthenticatorError> {
+    let authorization = bearer_authorization(token)?;
+
+    let headers = request.headers_mut();
+    headers.insert(AUTHORIZATION, authorization);
+    headers.entry(USER_AGENT).or_insert(user_agent);
+
+    Ok(request)
+}
@@ -0,0 +1,119 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::fmt::Display;
+use tracing::Level;
+
+/// Controls what the authenticator logs about token requests and the requests it sends on behalf
+/// of the app. Configured via
+/// [`GitHubAppAuthenticator::with_logging_policy`](crate::GitHubAppAuthenticator::with_logging_policy).
+///
+/// By default, token requests and failed requests are logged at the info level along with the
+/// bodies of failed responses, internal failures such as undecodable responses are logged at the
+/// error level, and ids are logged as is.
+#[derive(Clone, Debug)]
+pub struct LoggingPolicy {
+    token_requests: Option<Level>,
+    request_failures: Option<Level>,
+    internal_errors: Option<Level>,
+    redact_ids: bool,
+    response_bodies: bool,
+}
+
+impl Default for LoggingPolicy {
+    fn default() -> Self {
+        Self {
+            token_requests: Some(Level::INFO),
+            request_failures: Some(Level::INFO),
+            internal_errors: Some(Level::ERROR),
+            redact_ids: false,
+            response_bodies: true,
+        }
+    }
+}
+
+impl LoggingPolicy {
+    /// Configure the level that token requests, retries and revocations are logged at, along with
+    /// other requests sent on behalf of the app and webhook driven changes, or `None` to not log
+    /// them.
+    pub fn with_token_requests(mut self, level: Option<Level>) -> Self {
+        self.token_requests = level;
+        self
+    }
+
+    /// Configure the level that requests GitHub responded to with an error status are logged at,
+    /// along with detected clock skew and the circuit breaker opening, or `None` to not log them.
+    pub fn with_request_failures(mut self, level: Option<Level>) -> Self {
+        self.request_failures = level;
+        self
+    }
+
+    /// Configure the level that failures to encode requests, decode responses or generate JWTs
+    /// are logged at, or `None` to not log them.
+    pub fn with_internal_errors(mut self, level: Option<Level>) -> Self {
+        self.internal_errors = level;
+        self
+    }
+
+    /// Configure whether app and installation ids, along with urls and errors that may contain
+    /// them, are replaced by `<redacted>` in logs and spans.
+    pub fn with_redacted_ids(mut self, redact: bool) -> Self {
+        self.redact_ids = redact;
+        self
+    }
+
+    /// Configure whether the bodies of failed responses are logged.
+    pub fn with_response_bodies(mut self, log: bool) -> Self {
+        self.response_bodies = log;
+        self
+    }
+
+    pub(crate) fn token_requests(&self) -> Option<Level> {
+        self.token_requests
+    }
+
+    pub(crate) fn request_failures(&self) -> Option<Level> {
+        self.request_failures
+    }
+
+    pub(crate) fn internal_errors(&self) -> Option<Level> {
+        self.internal_errors
+    }
+
+    // Format an id, or anything that contains one, for logging
+    pub(crate) fn id<T>(&self, id: T) -> String where T: Display {
+        if self.redact_ids {
+            "<redacted>".to_string()
+        } else {
+            id.to_string()
+        }
+    }
+
+    // Format the body of a failed response for logging
+    pub(crate) fn body(&self, body: &[u8]) -> String {
+        if self.response_bodies {
+            String::from_utf8_lossy(body).into_owned()
+        } else {
+            "<omitted>".to_string()
+        }
+    }
+}
+
+// Log an event at a level that is only known at runtime, or not at all if the level is `None`
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            Some(tracing::Level::ERROR) => tracing::error!($($arg)+),
+            Some(tracing::Level::WARN) => tracing::warn!($($arg)+),
+            Some(tracing::Level::INFO) => tracing::info!($($arg)+),
+            Some(tracing::Level::DEBUG) => tracing::debug!($($arg)+),
+            Some(_) => tracing::trace!($($arg)+),
+            None => {}
+        }
+    };
+}
+
+pub(crate) use log_at;
@@ -0,0 +1,318 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use futures_util::StreamExt;
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use crate::{logging::log_at, AccessToken, CacheStats, GitHubAppAuthenticator, GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator, TokenRequest, TokenStats};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::TokenStore;
+
+// The number of tokens revoked at a time by InstallationManager::revoke_all
+const REVOCATION_CONCURRENCY: usize = 10;
+
+/// Hands out refreshing authenticators for the installations of an app, keeping the most recently
+/// used authenticators (and therefore their tokens) alive. Cloning is cheap, and all clones share
+/// the same authenticators.
+#[derive(Clone)]
+pub struct InstallationManager {
+    app: GitHubAppAuthenticator,
+    request: Arc<TokenRequest>,
+    capacity: usize,
+    authenticators: Arc<Mutex<Authenticators>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    store: Option<Arc<dyn TokenStore>>,
+}
+
+#[derive(Debug, Default)]
+struct Authenticators {
+    // Authenticators keyed by installation id along with the tick at which they were last used
+    entries: HashMap<u32, (RefreshingGitHubInstallationAuthenticator, u64)>,
+    tick: u64,
+    evictions: u64,
+    // The cache lookups and tokens of authenticators that are no longer kept alive
+    retired: TokenStats,
+}
+
+impl Authenticators {
+    fn retire(&mut self, installation_id: u32) -> Option<RefreshingGitHubInstallationAuthenticator> {
+        let (authenticator, _) = self.entries.remove(&installation_id)?;
+        let stats = authenticator.stats();
+
+        self.retired.cache_hits += stats.cache_hits;
+        self.retired.cache_misses += stats.cache_misses;
+        self.retired.tokens_minted += stats.tokens_minted;
+
+        Some(authenticator)
+    }
+}
+
+/// A snapshot of the authenticators kept alive by an [`InstallationManager`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstallationManagerStats {
+    /// The number of authenticators currently kept alive.
+    pub size: usize,
+    /// The maximum number of authenticators kept alive.
+    pub capacity: usize,
+    /// The number of authenticators evicted to make room for others.
+    pub evictions: u64,
+    /// The token bookkeeping of each authenticator currently kept alive, by installation id.
+    pub installations: HashMap<u32, TokenStats>,
+}
+
+impl std::fmt::Debug for InstallationManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstallationManager")
+            .field("app", &self.app)
+            .field("request", &self.request)
+            .field("capacity", &self.capacity)
+            .field("authenticators", &self.authenticators)
+            .finish()
+    }
+}
+
+/// The outcome of [`InstallationManager::revoke_all`].
+#[derive(Debug, Default)]
+pub struct RevocationReport {
+    /// The installations whose tokens were revoked.
+    pub revoked: Vec<u32>,
+    /// The installations that had no token to revoke.
+    pub skipped: Vec<u32>,
+    /// The installations whose tokens could not be revoked, along with the reason.
+    pub failed: Vec<(u32, GitHubAuthenticatorError)>,
+}
+
+impl RevocationReport {
+    /// Check whether every token was revoked.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl InstallationManagerStats {
+    /// The total number of tokens fetched by the authenticators currently kept alive.
+    pub fn tokens_minted(&self) -> u64 {
+        self.installations.values().map(|stats| stats.tokens_minted).sum()
+    }
+}
+
+impl InstallationManager {
+    pub(crate) fn new(app: GitHubAppAuthenticator, request: TokenRequest, capacity: usize) -> Self {
+        Self {
+            app,
+            request: Arc::new(request),
+            capacity: capacity.max(1),
+            authenticators: Arc::new(Mutex::new(Authenticators::default())),
+            #[cfg(not(target_arch = "wasm32"))]
+            store: None,
+        }
+    }
+
+    /// Share the tokens of all installations through `store`, see
+    /// [`RefreshingGitHubInstallationAuthenticator::with_token_store`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_token_store<S>(mut self, store: S) -> Self where S: TokenStore + 'static {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Get the app whose installations this manager hands out authenticators for.
+    pub fn app(&self) -> &GitHubAppAuthenticator {
+        &self.app
+    }
+
+    /// Get the authenticator for an installation. If the manager is at capacity, the least
+    /// recently used authenticator is evicted to make room.
+    pub fn for_installation(&self, installation_id: u32) -> RefreshingGitHubInstallationAuthenticator {
+        let mut authenticators = self.authenticators.lock().unwrap();
+        authenticators.tick += 1;
+        let tick = authenticators.tick;
+
+        if let Some((authenticator, last_used)) = authenticators.entries.get_mut(&installation_id) {
+            *last_used = tick;
+            return authenticator.clone();
+        }
+
+        if authenticators.entries.len() >= self.capacity {
+            let oldest = authenticators
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(installation_id, _)| *installation_id);
+
+            if let Some(oldest) = oldest {
+                let logging = self.app.logging();
+                log_at!(logging.token_requests(), installation_id = %logging.id(oldest), "Evicting installation authenticator");
+                authenticators.retire(oldest);
+                authenticators.evictions += 1;
+            }
+        }
+
+        let authenticator = self
+            .app
+            .installation_authenticator(installation_id)
+            .into_refreshing(self.request.as_ref().clone());
+        #[cfg(not(target_arch = "wasm32"))]
+        let authenticator = match &self.store {
+            Some(store) => authenticator.with_shared_token_store(store.clone()),
+            None => authenticator,
+        };
+        authenticators.entries.insert(installation_id, (authenticator.clone(), tick));
+
+        authenticator
+    }
+
+    /// Get the authenticator for the installation that has access to a repository, given as
+    /// `owner/repo`.
+    pub async fn for_repo(&self, full_name: &str) -> Result<RefreshingGitHubInstallationAuthenticator, GitHubAuthenticatorError> {
+        let installation_id = self.app.repository_installation_id(full_name).await?;
+        Ok(self.for_installation(installation_id))
+    }
+
+    /// Get the authenticator for the installation on an organization.
+    pub async fn for_org(&self, org: &str) -> Result<RefreshingGitHubInstallationAuthenticator, GitHubAuthenticatorError> {
+        let installation_id = self.app.org_installation_id(org).await?;
+        Ok(self.for_installation(installation_id))
+    }
+
+    /// Fetch tokens for many installations at once, for instance to warm up the tokens of every
+    /// installation when a service starts. At most `concurrency` tokens are fetched at a time.
+    /// Installations that already have a token that is not about to expire are not fetched again.
+    ///
+    /// Returns the outcome for each installation by installation id. Authenticators are kept
+    /// alive as with [`Self::for_installation`], so prefetching more installations than the
+    /// capacity of the manager evicts some of the prefetched tokens again.
+    pub async fn prefetch<I>(&self, installation_ids: I, concurrency: usize) -> HashMap<u32, Result<AccessToken, GitHubAuthenticatorError>>
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        futures_util::stream::iter(installation_ids)
+            .map(|installation_id| async move {
+                let result = self.for_installation(installation_id).access_token_detailed().await;
+                (installation_id, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Revoke the tokens of every installation that this manager keeps alive, for instance in
+    /// response to a security incident, and drop all authenticators. Tokens in the token store are
+    /// revoked and removed from the store as well. Outstanding clones of the authenticators fetch
+    /// new tokens on their next request.
+    ///
+    /// Failures to revoke a token do not stop the remaining tokens from being revoked, and are
+    /// collected in the returned report.
+    pub async fn revoke_all(&self) -> RevocationReport {
+        let authenticators = {
+            let mut authenticators = self.authenticators.lock().unwrap();
+            let installation_ids = authenticators.entries.keys().copied().collect::<Vec<_>>();
+
+            installation_ids
+                .into_iter()
+                .filter_map(|installation_id| Some((installation_id, authenticators.retire(installation_id)?)))
+                .collect::<Vec<_>>()
+        };
+
+        let outcomes = futures_util::stream::iter(authenticators)
+            .map(|(installation_id, authenticator)| async move {
+                (installation_id, authenticator.revoke_everywhere().await)
+            })
+            .buffer_unordered(REVOCATION_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut report = RevocationReport::default();
+
+        for (installation_id, outcome) in outcomes {
+            match outcome {
+                Ok(true) => report.revoked.push(installation_id),
+                Ok(false) => report.skipped.push(installation_id),
+                Err(err) => {
+                    let logging = self.app.logging();
+                    log_at!(logging.request_failures(), ?err, installation_id = %logging.id(installation_id), "Failed to revoke installation access token");
+                    report.failed.push((installation_id, err));
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Drop the authenticator for an installation after the app has been uninstalled. Any
+    /// outstanding clones of the authenticator are marked as gone and fail further token requests
+    /// with [`GitHubAuthenticatorError::InstallationGone`].
+    pub fn remove(&self, installation_id: u32) {
+        if let Some(authenticator) = self.take(installation_id) {
+            authenticator.mark_gone();
+        }
+    }
+
+    // Get the authenticator for an installation if one is kept alive, without counting as a use
+    pub(crate) fn get(&self, installation_id: u32) -> Option<RefreshingGitHubInstallationAuthenticator> {
+        self.authenticators
+            .lock()
+            .unwrap()
+            .entries
+            .get(&installation_id)
+            .map(|(authenticator, _)| authenticator.clone())
+    }
+
+    // Stop keeping the authenticator for an installation alive
+    pub(crate) fn take(&self, installation_id: u32) -> Option<RefreshingGitHubInstallationAuthenticator> {
+        self.authenticators.lock().unwrap().retire(installation_id)
+    }
+
+    /// The number of authenticators currently kept alive.
+    pub fn len(&self) -> usize {
+        self.authenticators.lock().unwrap().entries.len()
+    }
+
+    /// Check whether no authenticators are currently kept alive.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Counters of the authenticators kept alive by this manager, including authenticators that
+    /// have since been evicted or removed. Each authenticator counts as a single entry of the
+    /// cache, and requests for a token that it serves with its current token count as hits.
+    pub fn cache_stats(&self) -> CacheStats {
+        let authenticators = self.authenticators.lock().unwrap();
+
+        authenticators.entries.values().fold(
+            CacheStats {
+                hits: authenticators.retired.cache_hits,
+                misses: authenticators.retired.cache_misses,
+                refreshes: authenticators.retired.tokens_minted,
+                evictions: authenticators.evictions,
+                size: authenticators.entries.len(),
+            },
+            |mut cache_stats, (authenticator, _)| {
+                let stats = authenticator.stats();
+                cache_stats.hits += stats.cache_hits;
+                cache_stats.misses += stats.cache_misses;
+                cache_stats.refreshes += stats.tokens_minted;
+                cache_stats
+            },
+        )
+    }
+
+    /// A snapshot of the authenticators currently kept alive and the tokens they have fetched.
+    pub fn stats(&self) -> InstallationManagerStats {
+        let authenticators = self.authenticators.lock().unwrap();
+
+        InstallationManagerStats {
+            size: authenticators.entries.len(),
+            capacity: self.capacity,
+            evictions: authenticators.evictions,
+            installations: authenticators
+                .entries
+                .iter()
+                .map(|(installation_id, (authenticator, _))| (*installation_id, authenticator.stats()))
+                .collect(),
+        }
+    }
+}
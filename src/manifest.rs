@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use http::{HeaderValue, Method, StatusCode};
+use serde::Deserialize;
+use std::fmt::Debug;
+
+use crate::{logging::log_at, Account, GitHubAppAuthenticator, GitHubAuthenticatorError, GitHubHost, HttpTransport, RequestFailure};
+
+/// Completes the app manifest flow by exchanging the temporary code that GitHub hands out after an
+/// app has been created from a manifest for the credentials of the new app.
+#[derive(Clone, Debug)]
+pub struct ManifestConverter {
+    // Used for its configuration only. It does not hold credentials of its own
+    app: GitHubAppAuthenticator,
+}
+
+impl ManifestConverter {
+    /// Create a converter for apps created on github.com.
+    pub fn new(user_agent: HeaderValue) -> Self {
+        Self {
+            app: GitHubAppAuthenticator::new(0, vec![], user_agent),
+        }
+    }
+
+    /// Configure the transport to send requests via. The transport is also used by authenticators
+    /// created via [`Self::authenticator`].
+    pub fn with_transport<T>(&mut self, transport: T) -> &mut Self where T: HttpTransport + 'static {
+        self.app.with_transport(transport);
+        self
+    }
+
+    /// Configure the GitHub deployment that apps are created on.
+    pub fn with_host(&mut self, host: GitHubHost) -> &mut Self {
+        self.app.with_host(host);
+        self
+    }
+
+    /// Configure base uri of the API to send requests to.
+    pub fn with_base_uri<T>(&mut self, base_endpoint: T) -> &mut Self where T: ToString {
+        self.app.with_base_uri(base_endpoint);
+        self
+    }
+
+    /// Exchange the code for the credentials of the newly created app. Codes expire an hour after
+    /// they have been issued and can only be exchanged once.
+    pub async fn convert(&self, code: &str) -> Result<AppManifestConversion, GitHubAuthenticatorError> {
+        let url = format!("{}/app-manifests/{}/conversions", self.app.base_endpoint(), code);
+        let response = self.app.send(Method::POST, &url, None, None).await?;
+
+        if response.status() == StatusCode::CREATED {
+            serde_json::from_slice(response.body()).map_err(|err| {
+                log_at!(self.app.logging().internal_errors(), ?err, "Failed to decode app manifest conversion response body");
+                GitHubAuthenticatorError::app_response_decode(err, response.body())
+            })
+        } else {
+            let status = response.status();
+            let body = self.app.logging().body(response.body());
+
+            log_at!(self.app.logging().request_failures(), ?status, ?body, "Failed to convert app manifest");
+
+            Err(GitHubAuthenticatorError::AppRequestFailed(Box::new(RequestFailure::from_response(&response))))
+        }
+    }
+
+    /// Create an authenticator for a newly created app that shares the configuration of this
+    /// converter.
+    pub fn authenticator(&self, conversion: &AppManifestConversion) -> GitHubAppAuthenticator {
+        self.app.for_app(conversion.id, conversion.pem.as_bytes().to_vec())
+    }
+}
+
+/// The credentials of an app created from a manifest. These are only handed out once and must be
+/// stored by the caller.
+#[derive(Clone, Deserialize)]
+pub struct AppManifestConversion {
+    pub id: u32,
+    pub slug: Option<String>,
+    pub name: String,
+    pub owner: Option<Account>,
+    pub html_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// The secret that webhook deliveries are signed with, if the manifest configured a webhook.
+    pub webhook_secret: Option<String>,
+    /// The private key of the app in PEM format.
+    pub pem: String,
+}
+
+impl Debug for AppManifestConversion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppManifestConversion")
+            .field("id", &self.id)
+            .field("slug", &self.slug)
+            .field("name", &self.name)
+            .field("owner", &self.owner)
+            .field("html_url", &self.html_url)
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+// Instrumentation via the `metrics` crate. Without the `metrics` feature every function is a no-op,
+// so that call sites do not need to be feature gated.
+//
+// Metrics emitted:
+//
+// * `github_app_authenticator_token_requests_total`: counter of installation token requests, by
+//   `app_id`, `installation_id` and the `status` that GitHub responded with (`none` if the request
+//   did not receive a response)
+// * `github_app_authenticator_token_refresh_duration_seconds`: histogram of the time taken by
+//   refreshing authenticators to fetch a new token, by `app_id` and `installation_id`
+// * `github_app_authenticator_token_cache_hits_total` and
+//   `github_app_authenticator_token_cache_misses_total`: counters of refreshing authenticator
+//   token lookups that were served from the cache or required a new token, by `app_id` and
+//   `installation_id`
+// * `github_app_authenticator_jwt_generations_total`: counter of JWTs generated, by `app_id`
+
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use crate::time::Timestamp;
+
+use crate::GitHubAuthenticatorError;
+
+pub(crate) fn token_request<T>(app_id: u32, installation_id: u32, result: &Result<T, GitHubAuthenticatorError>) {
+    #[cfg(feature = "metrics")]
+    {
+        let status = match result {
+            Ok(_) => http::StatusCode::CREATED.as_str().to_string(),
+            Err(err) => err.status().map(|status| status.as_str().to_string()).unwrap_or_else(|| "none".to_string()),
+        };
+
+        ::metrics::counter!(
+            "github_app_authenticator_token_requests_total",
+            "app_id" => app_id.to_string(),
+            "installation_id" => installation_id.to_string(),
+            "status" => status,
+        )
+        .increment(1);
+    }
+}
+
+pub(crate) fn token_refresh(app_id: u32, installation_id: u32, started: Timestamp) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(
+        "github_app_authenticator_token_refresh_duration_seconds",
+        "app_id" => app_id.to_string(),
+        "installation_id" => installation_id.to_string(),
+    )
+    .record(crate::time::to_std(crate::time::between(crate::time::now(), started)).as_secs_f64());
+}
+
+pub(crate) fn token_cache(app_id: u32, installation_id: u32, hit: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let name = if hit {
+            "github_app_authenticator_token_cache_hits_total"
+        } else {
+            "github_app_authenticator_token_cache_misses_total"
+        };
+
+        ::metrics::counter!(
+            name,
+            "app_id" => app_id.to_string(),
+            "installation_id" => installation_id.to_string(),
+        )
+        .increment(1);
+    }
+}
+
+pub(crate) fn jwt_generated(app_id: u32) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("github_app_authenticator_jwt_generations_total", "app_id" => app_id.to_string()).increment(1);
+}
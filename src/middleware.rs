@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use http::header::AUTHORIZATION;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+
+use crate::{token::bearer_authorization, RefreshingGitHubInstallationAuthenticator};
+
+/// Middleware for [`reqwest_middleware::ClientWithMiddleware`] that authenticates every request
+/// with an installation access token, replacing any existing Authorization header. Tokens are
+/// refreshed as they near expiry. The token is attached regardless of the destination of the
+/// request, so the middleware should only be used for clients that talk exclusively to GitHub.
+#[derive(Clone, Debug)]
+pub struct GitHubInstallationMiddleware {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+}
+
+impl GitHubInstallationMiddleware {
+    /// Create a middleware that authenticates requests with tokens from the given authenticator.
+    pub fn new(authenticator: RefreshingGitHubInstallationAuthenticator) -> Self {
+        Self { authenticator }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+impl Middleware for GitHubInstallationMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let token = self
+            .authenticator
+            .access_token()
+            .await
+            .map_err(reqwest_middleware::Error::middleware)?;
+
+        let authorization = bearer_authorization(&token).map_err(reqwest_middleware::Error::middleware)?;
+        req.headers_mut().insert(AUTHORIZATION, authorization);
+
+        next.run(req, extensions).await
+    }
+}
@@ -0,0 +1,669 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, Timestamp};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT}, HeaderValue, Method, Request, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt::Debug, sync::{Arc, RwLock}};
+use tokio::sync::Mutex;
+
+use crate::{logging::{log_at, LoggingPolicy}, token::bearer_authorization, Account, GitHubAppAuthenticator, GitHubAuthenticatorError, GitHubHost, HttpTransport, RequestFailure, TokenRequest};
+
+/// A client for the OAuth flows of a GitHub App, which produce user access tokens that act on
+/// behalf of a user of the app. Create a client for an existing app authenticator via
+/// [`GitHubAppAuthenticator::oauth_client`] to share its configuration.
+#[derive(Clone)]
+pub struct OAuthClient {
+    // Used for its configuration only. It does not hold credentials of its own
+    app: GitHubAppAuthenticator,
+    web_endpoint: String,
+    client_id: String,
+    client_secret: String,
+}
+
+impl Debug for OAuthClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthClient")
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct CodeExchange<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_uri: Option<&'a str>,
+}
+
+// The token endpoint responds with a 200 even if the request failed, reporting the failure in the
+// body instead
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OAuthResponse<T> {
+    Error {
+        error: String,
+        error_description: Option<String>,
+        // The polling interval to use from now on, sent along with `slow_down` errors
+        interval: Option<u64>,
+    },
+    Success(T),
+}
+
+#[derive(Serialize)]
+struct RefreshRequest<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    grant_type: &'static str,
+    refresh_token: &'a str,
+}
+
+#[derive(Serialize)]
+struct TokenReference<'a> {
+    access_token: &'a str,
+}
+
+#[derive(Serialize)]
+struct ScopedTokenBody<'a> {
+    access_token: &'a str,
+    #[serde(flatten)]
+    request: &'a ScopedTokenRequest,
+}
+
+/// A request for narrowing down a user access token via [`OAuthClient::scope_token`]. The target
+/// account must be given by either its login or its id.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ScopedTokenRequest {
+    /// The login of the user or organization to scope the token to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The id of the user or organization to scope the token to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<u64>,
+    /// The permissions and repositories to scope the token to. Defaults to those of the existing
+    /// token.
+    #[serde(flatten)]
+    pub request: TokenRequest,
+}
+
+#[derive(Serialize)]
+struct DeviceCodeRequest<'a> {
+    client_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct DeviceTokenRequest<'a> {
+    client_id: &'a str,
+    device_code: &'a str,
+    grant_type: &'static str,
+}
+
+#[derive(Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: i64,
+    interval: u64,
+}
+
+/// A pending authorization via the device flow. Show the user code and verification uri to the
+/// user, then poll for the token via [`OAuthClient::poll_device_token`] or wait for it via
+/// [`OAuthClient::wait_for_device_token`].
+#[derive(Clone)]
+pub struct DeviceAuthorization {
+    device_code: String,
+    /// The code that the user has to enter at the verification uri.
+    pub user_code: String,
+    /// The page at which the user enters the user code, usually `https://github.com/login/device`.
+    pub verification_uri: String,
+    /// The time after which the user code can no longer be entered.
+    pub expires_at: Timestamp,
+    /// The minimum number of seconds to wait between polls. GitHub raises the interval when it
+    /// is asked to slow down, which [`OAuthClient::poll_device_token`] keeps track of.
+    pub interval: u64,
+}
+
+impl Debug for DeviceAuthorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceAuthorization")
+            .field("user_code", &self.user_code)
+            .field("verification_uri", &self.verification_uri)
+            .field("expires_at", &self.expires_at)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl OAuthClient {
+    /// Create a client for an app registered on github.com.
+    pub fn new(client_id: String, client_secret: String, user_agent: HeaderValue) -> Self {
+        Self::for_app(GitHubAppAuthenticator::new(0, vec![], user_agent), client_id, client_secret)
+    }
+
+    pub(crate) fn for_app(app: GitHubAppAuthenticator, client_id: String, client_secret: String) -> Self {
+        Self {
+            web_endpoint: app.host().web_endpoint(),
+            app,
+            client_id,
+            client_secret,
+        }
+    }
+
+    /// Configure the transport to send requests via.
+    pub fn with_transport<T>(&mut self, transport: T) -> &mut Self where T: HttpTransport + 'static {
+        self.app.with_transport(transport);
+        self
+    }
+
+    /// Configure the GitHub deployment that the app is registered on.
+    pub fn with_host(&mut self, host: GitHubHost) -> &mut Self {
+        self.web_endpoint = host.web_endpoint();
+        self.app.with_host(host);
+        self
+    }
+
+    /// Configure base uri of the API to send requests to.
+    pub fn with_base_uri<T>(&mut self, base_endpoint: T) -> &mut Self where T: ToString {
+        self.app.with_base_uri(base_endpoint);
+        self
+    }
+
+    /// Configure base uri of the web interface, which serves the OAuth endpoints.
+    pub fn with_web_base_uri<T>(&mut self, web_endpoint: T) -> &mut Self where T: ToString {
+        self.web_endpoint = web_endpoint.to_string();
+        self
+    }
+
+    /// Configure what the client logs about its requests. Clients created via
+    /// [`GitHubAppAuthenticator::oauth_client`] start out with the policy of the app.
+    pub fn with_logging_policy(&mut self, policy: LoggingPolicy) -> &mut Self {
+        self.app.with_logging_policy(policy);
+        self
+    }
+
+    /// The client id of the app.
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The url to send users to in order to authorize the app. GitHub redirects users back to
+    /// `redirect_uri`, or to the callback url of the app if none is given, along with a `code` to
+    /// exchange via [`Self::exchange_code`] and the given `state`.
+    pub fn authorize_url(&self, redirect_uri: Option<&str>, state: &str) -> String {
+        let mut url = format!(
+            "{}/login/oauth/authorize?client_id={}&state={}",
+            self.web_endpoint,
+            encode(&self.client_id),
+            encode(state)
+        );
+
+        if let Some(redirect_uri) = redirect_uri {
+            url.push_str(&format!("&redirect_uri={}", encode(redirect_uri)));
+        }
+
+        url
+    }
+
+    /// Exchange the code that GitHub hands out after a user has authorized the app for a user
+    /// access token. `redirect_uri` must match the one used to start the authorization, if any.
+    /// Codes expire 10 minutes after they have been issued and can only be exchanged once.
+    pub async fn exchange_code(&self, code: &str, redirect_uri: Option<&str>) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        let request = CodeExchange {
+            client_id: &self.client_id,
+            client_secret: &self.client_secret,
+            code,
+            redirect_uri,
+        };
+
+        let token: UserAccessTokenResponse = self.post_oauth("/login/oauth/access_token", &request).await?;
+
+        Ok(token.into_access_token())
+    }
+
+    /// Exchange a refresh token for a new user access token. The refresh token can not be used
+    /// again afterwards, use the refresh token of the returned token instead. Fails with
+    /// [`GitHubAuthenticatorError::ReauthorizationRequired`] if GitHub no longer accepts the
+    /// refresh token.
+    pub async fn refresh_user_token(&self, refresh_token: &str) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        let request = RefreshRequest {
+            client_id: &self.client_id,
+            client_secret: &self.client_secret,
+            grant_type: "refresh_token",
+            refresh_token,
+        };
+
+        match self.post_oauth::<_, UserAccessTokenResponse>("/login/oauth/access_token", &request).await {
+            Ok(token) => Ok(token.into_access_token()),
+            Err(GitHubAuthenticatorError::OAuthRequestFailed(error)) if error == "bad_refresh_token" => {
+                Err(GitHubAuthenticatorError::ReauthorizationRequired)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Create an authenticator that keeps a user access token alive by refreshing it before it
+    /// expires.
+    pub fn into_refreshing(self, token: UserAccessToken) -> RefreshingUserAuthenticator {
+        RefreshingUserAuthenticator::new(self, token)
+    }
+
+    /// Check whether a user access token issued to the app is still valid. Returns `None` if GitHub
+    /// does not accept the token.
+    pub async fn check_token(&self, access_token: &str) -> Result<Option<TokenAuthorization>, GitHubAuthenticatorError> {
+        let response = self.send_as_client(Method::POST, "/token", &TokenReference { access_token }).await?;
+
+        match response.status() {
+            StatusCode::OK => decode_authorization(&response, self.app.logging()).map(Some),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(client_request_failed(status, &response, self.app.logging())),
+        }
+    }
+
+    /// Invalidate a user access token issued to the app and issue a new token in its place,
+    /// with the same expiration.
+    pub async fn reset_token(&self, access_token: &str) -> Result<TokenAuthorization, GitHubAuthenticatorError> {
+        let response = self.send_as_client(Method::PATCH, "/token", &TokenReference { access_token }).await?;
+
+        match response.status() {
+            StatusCode::OK => decode_authorization(&response, self.app.logging()),
+            status => Err(client_request_failed(status, &response, self.app.logging())),
+        }
+    }
+
+    /// Create a user access token that is limited to a subset of the permissions and
+    /// repositories of an existing token, for handing to jobs that only need narrow access. The
+    /// existing token remains valid.
+    pub async fn scope_token(&self, access_token: &str, request: &ScopedTokenRequest) -> Result<TokenAuthorization, GitHubAuthenticatorError> {
+        let body = ScopedTokenBody {
+            access_token,
+            request,
+        };
+
+        let response = self.send_as_client(Method::POST, "/token/scoped", &body).await?;
+
+        match response.status() {
+            StatusCode::OK => decode_authorization(&response, self.app.logging()),
+            status => Err(client_request_failed(status, &response, self.app.logging())),
+        }
+    }
+
+    /// Revoke a user access token issued to the app.
+    pub async fn delete_token(&self, access_token: &str) -> Result<(), GitHubAuthenticatorError> {
+        let response = self.send_as_client(Method::DELETE, "/token", &TokenReference { access_token }).await?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            status => Err(client_request_failed(status, &response, self.app.logging())),
+        }
+    }
+
+    /// Revoke the user's authorization of the app, which revokes all tokens that the app holds
+    /// for the user. The user has to authorize the app again before it can act on their behalf.
+    pub async fn delete_grant(&self, access_token: &str) -> Result<(), GitHubAuthenticatorError> {
+        let response = self.send_as_client(Method::DELETE, "/grant", &TokenReference { access_token }).await?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            status => Err(client_request_failed(status, &response, self.app.logging())),
+        }
+    }
+
+    // Send a request about a user access token to an application endpoint of the API,
+    // authenticated via the client id and secret
+    async fn send_as_client<T>(&self, method: Method, path: &str, body: &T) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError>
+    where
+        T: Serialize,
+    {
+        let url = format!("{}/applications/{}{}", self.app.base_endpoint(), self.client_id, path);
+        let body = serde_json::to_vec(body).map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+        let credentials = STANDARD.encode(format!("{}:{}", self.client_id, self.client_secret));
+        let mut authorization = HeaderValue::from_str(&format!("Basic {}", credentials))
+            .map_err(GitHubAuthenticatorError::FailedToCreateHeader)?;
+        authorization.set_sensitive(true);
+
+        let logging = self.app.logging();
+        log_at!(logging.token_requests(), ?method, url = %logging.id(&url), "Sending application token request");
+
+        let request = Request::builder()
+            .method(method)
+            .uri(&url)
+            .header(USER_AGENT, self.app.user_agent())
+            .header(AUTHORIZATION, authorization)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(|err| GitHubAuthenticatorError::Transport(Box::new(err)))?;
+
+        self.app.dispatch(request).await
+    }
+
+    /// Start an authorization via the device flow, which lets users of a CLI or other device
+    /// without a browser authorize the app on another device. The device flow must be enabled in
+    /// the settings of the app.
+    pub async fn request_device_code(&self) -> Result<DeviceAuthorization, GitHubAuthenticatorError> {
+        let request = DeviceCodeRequest {
+            client_id: &self.client_id,
+        };
+
+        let response: DeviceCodeResponse = self.post_oauth("/login/device/code", &request).await?;
+
+        Ok(DeviceAuthorization {
+            device_code: response.device_code,
+            user_code: response.user_code,
+            verification_uri: response.verification_uri,
+            expires_at: time::now() + time::seconds(response.expires_in),
+            interval: response.interval,
+        })
+    }
+
+    /// Check once whether the user has completed a device authorization. Returns `None` while the
+    /// authorization is pending. Callers must wait for at least `authorization.interval` seconds
+    /// between polls. Fails with [`GitHubAuthenticatorError::OAuthRequestFailed`] once the user
+    /// has denied the authorization or the device code has expired.
+    pub async fn poll_device_token(&self, authorization: &mut DeviceAuthorization) -> Result<Option<UserAccessToken>, GitHubAuthenticatorError> {
+        let request = DeviceTokenRequest {
+            client_id: &self.client_id,
+            device_code: &authorization.device_code,
+            grant_type: "urn:ietf:params:oauth:grant-type:device_code",
+        };
+
+        match self.post_oauth_response::<_, UserAccessTokenResponse>("/login/oauth/access_token", &request).await? {
+            OAuthResponse::Success(token) => Ok(Some(token.into_access_token())),
+            OAuthResponse::Error { error, .. } if error == "authorization_pending" => Ok(None),
+            OAuthResponse::Error { error, interval, .. } if error == "slow_down" => {
+                // GitHub adds 5 seconds to the interval for every violation
+                authorization.interval = interval.unwrap_or(authorization.interval + 5);
+                log_at!(self.app.logging().token_requests(), interval = ?authorization.interval, "Slowing down device token polling");
+                Ok(None)
+            }
+            OAuthResponse::Error { error, error_description, .. } => {
+                log_at!(self.app.logging().request_failures(), ?error, ?error_description, "Device authorization failed");
+                Err(GitHubAuthenticatorError::OAuthRequestFailed(error))
+            }
+        }
+    }
+
+    /// Poll for the token of a device authorization until the user has completed it, respecting
+    /// the polling interval requested by GitHub.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn wait_for_device_token(&self, authorization: &DeviceAuthorization) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        let mut authorization = authorization.clone();
+
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(authorization.interval)).await;
+
+            if let Some(token) = self.poll_device_token(&mut authorization).await? {
+                return Ok(token);
+            }
+        }
+    }
+
+    // Send a request to an OAuth endpoint of the web interface. Failures are reported with a 200
+    // and an error code in the body, which is surfaced as the error.
+    pub(crate) async fn post_oauth<T, R>(&self, path: &str, body: &T) -> Result<R, GitHubAuthenticatorError>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        match self.post_oauth_response(path, body).await? {
+            OAuthResponse::Success(body) => Ok(body),
+            OAuthResponse::Error { error, error_description, .. } => {
+                log_at!(self.app.logging().request_failures(), ?error, ?error_description, ?path, "OAuth request was rejected");
+                Err(GitHubAuthenticatorError::OAuthRequestFailed(error))
+            }
+        }
+    }
+
+    async fn post_oauth_response<T, R>(&self, path: &str, body: &T) -> Result<OAuthResponse<R>, GitHubAuthenticatorError>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let url = format!("{}{}", self.web_endpoint, path);
+        let body = serde_json::to_vec(body).map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&url)
+            .header(USER_AGENT, self.app.user_agent())
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(|err| GitHubAuthenticatorError::Transport(Box::new(err)))?;
+
+        let response = self.app.dispatch(request).await?;
+
+        let logging = self.app.logging();
+
+        if response.status() != StatusCode::OK {
+            let status = response.status();
+            let body = logging.body(response.body());
+
+            log_at!(logging.request_failures(), ?status, ?body, ?url, "OAuth request failed");
+
+            return Err(GitHubAuthenticatorError::OAuthRequestFailed(status.to_string()));
+        }
+
+        serde_json::from_slice(response.body()).map_err(|err| {
+            log_at!(logging.internal_errors(), ?err, ?url, "Failed to decode OAuth response body");
+            GitHubAuthenticatorError::access_token_decode(err, response.body())
+        })
+    }
+}
+
+fn decode_authorization(response: &Response<Vec<u8>>, logging: &LoggingPolicy) -> Result<TokenAuthorization, GitHubAuthenticatorError> {
+    serde_json::from_slice(response.body()).map_err(|err| {
+        log_at!(logging.internal_errors(), ?err, "Failed to decode token authorization");
+        GitHubAuthenticatorError::app_response_decode(err, response.body())
+    })
+}
+
+fn client_request_failed(status: StatusCode, response: &Response<Vec<u8>>, logging: &LoggingPolicy) -> GitHubAuthenticatorError {
+    let body = logging.body(response.body());
+
+    log_at!(logging.request_failures(), ?status, ?body, "Application token request failed");
+
+    GitHubAuthenticatorError::AppRequestFailed(Box::new(RequestFailure::from_response(response)))
+}
+
+// Percent-encode a query parameter value
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UserAccessTokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+    refresh_token_expires_in: Option<i64>,
+    #[serde(default)]
+    scope: String,
+    token_type: String,
+}
+
+impl UserAccessTokenResponse {
+    // Lifetimes are reported relative to the time of the response
+    pub(crate) fn into_access_token(self) -> UserAccessToken {
+        let now = time::now();
+
+        UserAccessToken {
+            token: self.access_token,
+            expires_at: self.expires_in.map(|seconds| now + time::seconds(seconds)),
+            refresh_token: self.refresh_token,
+            refresh_token_expires_at: self.refresh_token_expires_in.map(|seconds| now + time::seconds(seconds)),
+            scope: self.scope,
+            token_type: self.token_type,
+        }
+    }
+}
+
+/// A user access token, which acts on behalf of a user within the permissions of the app.
+#[derive(Clone)]
+pub struct UserAccessToken {
+    /// The access token to send as a bearer token.
+    pub token: String,
+    /// The time at which GitHub will stop accepting the token. Tokens do not expire if the app has
+    /// opted out of expiring user tokens.
+    pub expires_at: Option<Timestamp>,
+    /// A token that can be exchanged for a new access token once this one has expired. Only
+    /// handed out for expiring tokens.
+    pub refresh_token: Option<String>,
+    /// The time at which GitHub will stop accepting the refresh token.
+    pub refresh_token_expires_at: Option<Timestamp>,
+    /// The OAuth scopes of the token. Always empty for GitHub Apps, whose tokens are limited by
+    /// the app's permissions instead.
+    pub scope: String,
+    pub token_type: String,
+}
+
+impl UserAccessToken {
+    /// Create an Authorization header value that authenticates requests with this token. The
+    /// value is marked as sensitive so that it is omitted from debug output.
+    pub fn authorization_header(&self) -> Result<HeaderValue, GitHubAuthenticatorError> {
+        bearer_authorization(&self.token)
+    }
+}
+
+impl Debug for UserAccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserAccessToken")
+            .field("expires_at", &self.expires_at)
+            .field("refresh_token_expires_at", &self.refresh_token_expires_at)
+            .field("scope", &self.scope)
+            .field("token_type", &self.token_type)
+            .finish()
+    }
+}
+
+/// A user access token as known to GitHub, along with the user that it acts on behalf of.
+#[derive(Clone, Deserialize)]
+pub struct TokenAuthorization {
+    pub id: u64,
+    /// The token itself. Only meaningful for newly issued tokens, such as those returned by
+    /// [`OAuthClient::reset_token`].
+    pub token: String,
+    /// The last eight characters of the token, for identifying it without revealing it.
+    pub token_last_eight: Option<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    pub expires_at: Option<Timestamp>,
+    pub user: Option<Account>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl Debug for TokenAuthorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenAuthorization")
+            .field("id", &self.id)
+            .field("token_last_eight", &self.token_last_eight)
+            .field("scopes", &self.scopes)
+            .field("expires_at", &self.expires_at)
+            .field("user", &self.user)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .finish()
+    }
+}
+
+/// An authenticator for continually providing a user access token, exchanging the refresh token
+/// for a new token before the current one expires. Cloning is cheap, and all clones share the same
+/// token. Once the refresh token has expired as well, the user has to authorize the app again.
+#[derive(Clone, Debug)]
+pub struct RefreshingUserAuthenticator {
+    client: Arc<OAuthClient>,
+    token: Arc<RwLock<UserAccessToken>>,
+    refresh_lock: Arc<Mutex<()>>,
+}
+
+impl RefreshingUserAuthenticator {
+    fn new(client: OAuthClient, token: UserAccessToken) -> Self {
+        Self {
+            client: Arc::new(client),
+            token: Arc::new(RwLock::new(token)),
+            refresh_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    // Refresh 5 minutes before the expiration time that GitHub specifies to alleviate potential
+    // clock skew and race conditions
+    fn cached_token(&self) -> Option<UserAccessToken> {
+        let token = self.token.read().unwrap();
+
+        match token.expires_at {
+            Some(expires_at) if expires_at - time::minutes(5) <= time::now() => None,
+            _ => Some(token.clone()),
+        }
+    }
+
+    /// Get a user access token, refreshing the current token if it is about to expire.
+    pub async fn access_token(&self) -> Result<String, GitHubAuthenticatorError> {
+        Ok(self.access_token_detailed().await?.token)
+    }
+
+    /// Get a user access token along with its expiration times, refreshing the current token if
+    /// it is about to expire.
+    pub async fn access_token_detailed(&self) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        let _guard = self.refresh_lock.lock().await;
+
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        self.store_token().await
+    }
+
+    /// Exchange the refresh token for a new user access token regardless of whether the current
+    /// token has expired.
+    pub async fn refresh(&self) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        let _guard = self.refresh_lock.lock().await;
+        self.store_token().await
+    }
+
+    /// Check whether the user has to authorize the app again, because the current token has
+    /// expired and can not be refreshed.
+    pub fn needs_reauthorization(&self) -> bool {
+        self.cached_token().is_none() && self.refresh_token().is_none()
+    }
+
+    // Get the refresh token if it is still accepted
+    fn refresh_token(&self) -> Option<String> {
+        let token = self.token.read().unwrap();
+
+        match token.refresh_token_expires_at {
+            Some(expires_at) if expires_at <= time::now() => None,
+            _ => token.refresh_token.clone(),
+        }
+    }
+
+    // Callers must hold the refresh lock
+    async fn store_token(&self) -> Result<UserAccessToken, GitHubAuthenticatorError> {
+        let refresh_token = self
+            .refresh_token()
+            .ok_or(GitHubAuthenticatorError::ReauthorizationRequired)?;
+
+        let token = self.client.refresh_user_token(&refresh_token).await?;
+        *self.token.write().unwrap() = token.clone();
+
+        Ok(token)
+    }
+}
@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use http::{Method, Response, StatusCode};
+use std::time::Duration;
+
+use crate::GitHubAuthenticatorError;
+
+/// Observes every request that an app authenticator and its installation authenticators send to
+/// GitHub, for instance to feed logging, metrics or inspection pipelines. An observer configured
+/// via [`GitHubAppAuthenticator::with_request_observer`](crate::GitHubAppAuthenticator::with_request_observer)
+/// is invoked synchronously once the response has been received or the request has failed, and
+/// should hand observations off rather than block.
+pub trait RequestObserver: Send + Sync {
+    fn observe(&self, request: &ObservedRequest);
+}
+
+/// A sanitized view of a request to GitHub and its outcome. Observations never include
+/// credentials, query strings or bodies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObservedRequest {
+    pub method: Method,
+    /// The path of the request, without the query string.
+    pub path: String,
+    /// The status that GitHub responded with, if it responded.
+    pub status: Option<StatusCode>,
+    /// The time taken to send the request and receive the response.
+    pub latency: Duration,
+    /// The id that GitHub assigned to the request, as reported by the `X-GitHub-Request-Id`
+    /// header.
+    pub request_id: Option<String>,
+    /// A description of the failure, if the request could not be sent.
+    pub error: Option<String>,
+}
+
+impl ObservedRequest {
+    pub(crate) fn new(
+        method: Method,
+        path: &str,
+        result: &Result<Response<Vec<u8>>, GitHubAuthenticatorError>,
+        latency: Duration,
+    ) -> Self {
+        let (status, request_id, error) = match result {
+            Ok(response) => (
+                Some(response.status()),
+                response
+                    .headers()
+                    .get("x-github-request-id")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                None,
+            ),
+            Err(err) => (None, None, Some(err.to_string())),
+        };
+
+        Self {
+            method,
+            path: path.to_string(),
+            status,
+            latency,
+            request_id,
+            error,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::{sync::{Arc, Mutex}, time::Duration};
+use tokio::{sync::{Semaphore, SemaphorePermit}, time::Instant};
+
+/// Paces the requests sent to GitHub to stay clear of its secondary rate limits, which are
+/// triggered by sending too many requests concurrently or in quick succession. A pacer configured
+/// via [`GitHubAppAuthenticator::with_pacer`](crate::GitHubAppAuthenticator::with_pacer) applies
+/// to all requests of the app, including those of its installation authenticators. Cloning is
+/// cheap, and all clones share the same limits.
+#[derive(Clone, Debug)]
+pub struct RequestPacer {
+    permits: Arc<Semaphore>,
+    interval: Duration,
+    // The earliest time at which the next request may be sent
+    next: Arc<Mutex<Instant>>,
+}
+
+impl RequestPacer {
+    /// Create a pacer that allows for at most `max_concurrent` requests in flight at any time, and
+    /// that starts at most `max_per_second` requests per second. Requests are spread out evenly
+    /// rather than sent in bursts.
+    pub fn new(max_concurrent: usize, max_per_second: u32) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            interval: Duration::from_secs(1) / max_per_second.max(1),
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // Wait until a request may be sent. The request must be sent while holding the returned permit
+    pub(crate) async fn acquire(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed
+        let permit = self.permits.acquire().await.expect("pacer semaphore closed");
+
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+
+        permit
+    }
+}
@@ -4,32 +4,39 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
 
-/// Capability permission level.
-#[derive(Debug, Serialize)]
+use crate::GitHubAuthenticatorError;
+
+/// Capability permission level. Levels are ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ReadOnly {
     Read,
 }
 
-/// Capability permission level.
-#[derive(Debug, Serialize)]
+/// Capability permission level. Levels are ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum WriteOnly {
     Write,
 }
 
-/// Capability permission level.
-#[derive(Debug, Serialize)]
+/// Capability permission level. Levels are ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ReadWrite {
     Read,
     Write,
 }
 
-/// Capability permission level.
-#[derive(Debug, Serialize)]
+/// Capability permission level. Levels are ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ReadWriteAdmin {
     Read,
@@ -37,75 +44,225 @@ pub enum ReadWriteAdmin {
     Admin,
 }
 
-/// The permissions that can be assigned to an access token.
-#[derive(Debug, Default, Serialize)]
-pub struct Permissions {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub actions: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub administration: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub checks: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contents: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub deployments: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub environments: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub issues: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub packages: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub pages: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub pull_requests: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub repository_hooks: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub repository_projects: Option<ReadWriteAdmin>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub secret_scanning_alerts: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub secrets: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub security_events: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub single_file: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub statuses: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub vulnerability_alerts: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub workflows: Option<WriteOnly>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub members: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_administration: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_custom_roles: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_announcement_banners: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_hooks: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_personal_access_tokens: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_personal_access_token_requests: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_plan: Option<ReadOnly>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_projects: Option<ReadWriteAdmin>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_packages: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_secrets: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_self_hosted_runners: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub organization_user_blocking: Option<ReadWrite>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub team_discussions: Option<ReadWrite>,
+impl Permissions {
+    /// Read access to repository contents, for workloads that clone or inspect repositories.
+    /// This and the other presets are convenient starting points, and may be narrowed further
+    /// before requesting a token.
+    pub fn contents_read_only() -> Self {
+        Self::default()
+            .with_contents(ReadWrite::Read)
+            .with_metadata(ReadWrite::Read)
+    }
+
+    /// Write access to check runs along with read access to repository contents, for workloads
+    /// that report results of analyzing a commit.
+    pub fn checks_writer() -> Self {
+        Self::contents_read_only().with_checks(ReadWrite::Write)
+    }
+
+    /// Access for workloads driving GitHub Actions: managing workflow runs and artifacts, and
+    /// reporting checks and commit statuses.
+    pub fn actions_ci() -> Self {
+        Self::checks_writer()
+            .with_actions(ReadWrite::Write)
+            .with_statuses(ReadWrite::Write)
+    }
+}
+
+/// Parses permissions from a comma separated list of `name=level` pairs, for instance
+/// `contents=read,issues=write`, as accepted on command lines.
+impl FromStr for Permissions {
+    type Err = GitHubAuthenticatorError;
+
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| GitHubAuthenticatorError::InvalidPermissions(reason);
+
+        let mut pairs = serde_json::Map::new();
+
+        for pair in list.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (name, level) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("{} is not of the form name=level", pair)))?;
+
+            pairs.insert(name.trim().to_string(), level.trim().into());
+        }
+
+        let permissions: Permissions = serde_json::from_value(pairs.clone().into())
+            .map_err(|err| invalid(err.to_string()))?;
+
+        // Names that are not permissions are ignored when deserializing
+        let known = serde_json::to_value(&permissions).map_err(|err| invalid(err.to_string()))?;
+        if let Some(unknown) = pairs.keys().find(|name| known.get(name).is_none()) {
+            return Err(invalid(format!("{} is not a known permission", unknown)));
+        }
+
+        Ok(permissions)
+    }
 }
+
+/// A permission level independent of the levels that a specific permission supports.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionLevel {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Display for PermissionLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PermissionLevel::Read => write!(f, "read"),
+            PermissionLevel::Write => write!(f, "write"),
+            PermissionLevel::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl From<ReadOnly> for PermissionLevel {
+    fn from(_: ReadOnly) -> Self {
+        PermissionLevel::Read
+    }
+}
+
+impl From<WriteOnly> for PermissionLevel {
+    fn from(_: WriteOnly) -> Self {
+        PermissionLevel::Write
+    }
+}
+
+impl From<ReadWrite> for PermissionLevel {
+    fn from(value: ReadWrite) -> Self {
+        match value {
+            ReadWrite::Read => PermissionLevel::Read,
+            ReadWrite::Write => PermissionLevel::Write,
+        }
+    }
+}
+
+impl From<ReadWriteAdmin> for PermissionLevel {
+    fn from(value: ReadWriteAdmin) -> Self {
+        match value {
+            ReadWriteAdmin::Read => PermissionLevel::Read,
+            ReadWriteAdmin::Write => PermissionLevel::Write,
+            ReadWriteAdmin::Admin => PermissionLevel::Admin,
+        }
+    }
+}
+
+/// A permission that was requested at a higher level than has been granted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionMismatch {
+    /// The name of the permission.
+    pub permission: &'static str,
+    /// The requested level.
+    pub requested: PermissionLevel,
+    /// The granted level, if the permission has been granted at all.
+    pub granted: Option<PermissionLevel>,
+}
+
+impl Display for PermissionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.granted {
+            Some(granted) => write!(
+                f,
+                "requested `{}: {}` but only `{}` has been granted",
+                self.permission, self.requested, granted
+            ),
+            None => write!(
+                f,
+                "requested `{}: {}` but it has not been granted",
+                self.permission, self.requested
+            ),
+        }
+    }
+}
+
+trait Level: Sized {
+    const READ: Option<Self>;
+}
+
+impl Level for ReadOnly {
+    const READ: Option<Self> = Some(ReadOnly::Read);
+}
+
+impl Level for WriteOnly {
+    const READ: Option<Self> = None;
+}
+
+impl Level for ReadWrite {
+    const READ: Option<Self> = Some(ReadWrite::Read);
+}
+
+impl Level for ReadWriteAdmin {
+    const READ: Option<Self> = Some(ReadWriteAdmin::Read);
+}
+
+macro_rules! permissions_impl {
+    ($($with:ident => $field:ident: $level:ty),* $(,)?) => {
+        impl Permissions {
+            /// Read access to everything that can be read. Permissions that only support write
+            /// access are not granted.
+            pub fn read_all() -> Self {
+                Self {
+                    $($field: <$level as $crate::permissions::Level>::READ,)*
+                }
+            }
+
+            /// Combine two sets of permissions, granting each permission at the higher of the two
+            /// levels.
+            pub fn merge(&self, other: &Self) -> Self {
+                Self {
+                    $($field: self.$field.max(other.$field),)*
+                }
+            }
+
+            /// Restrict two sets of permissions to the permissions they have in common, granting
+            /// each permission at the lower of the two levels.
+            pub fn intersect(&self, other: &Self) -> Self {
+                Self {
+                    $($field: self.$field.min(other.$field),)*
+                }
+            }
+
+            /// Check that every permission is also granted by `other` at the same or a higher
+            /// level.
+            pub fn is_subset_of(&self, other: &Self) -> bool {
+                true $(&& self.$field <= other.$field)*
+            }
+
+            /// List the permissions that are not granted by `granted` at the same or a higher
+            /// level.
+            pub fn mismatches(&self, granted: &Self) -> Vec<$crate::permissions::PermissionMismatch> {
+                let mut mismatches = vec![];
+
+                $(
+                    if let Some(requested) = self.$field {
+                        if Some(requested) > granted.$field {
+                            mismatches.push($crate::permissions::PermissionMismatch {
+                                permission: stringify!($field),
+                                requested: requested.into(),
+                                granted: granted.$field.map(Into::into),
+                            });
+                        }
+                    }
+                )*
+
+                mismatches
+            }
+
+            $(
+                #[doc = concat!("Grant the `", stringify!($field), "` permission at the given level.")]
+                pub fn $with(mut self, level: $level) -> Self {
+                    self.$field = Some(level);
+                    self
+                }
+            )*
+        }
+    };
+}
+
+mod generated;
+
+pub use generated::Permissions;
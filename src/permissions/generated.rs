@@ -0,0 +1,262 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+// This file is generated by `cargo xtask permissions` from GitHub's OpenAPI description. Do not
+// edit it by hand.
+
+use serde::{Deserialize, Serialize};
+
+use super::{ReadOnly, ReadWrite, ReadWriteAdmin, WriteOnly};
+
+/// The permissions that can be assigned to an access token.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Permissions {
+    /// The level of permission to grant the access token for GitHub Actions workflows, workflow
+    /// runs, and artifacts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage GitHub Actions variables of a
+    /// repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions_variables: Option<ReadWrite>,
+    /// The level of permission to grant the access token for repository creation, deletion,
+    /// settings, teams, and collaborators creation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub administration: Option<ReadWrite>,
+    /// The level of permission to create and retrieve the access token for repository attestations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestations: Option<ReadWrite>,
+    /// The level of permission to grant the access token for checks on code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checks: Option<ReadWrite>,
+    /// The level of permission to grant the access token to create, edit, delete, and list
+    /// Codespaces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codespaces: Option<ReadWrite>,
+    /// The level of permission to grant the access token for repository contents, commits,
+    /// branches, downloads, releases, and merges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage Dependabot secrets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependabot_secrets: Option<ReadWrite>,
+    /// The level of permission to grant the access token for deployments and deployment statuses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployments: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage the email addresses belonging to
+    /// a user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_addresses: Option<ReadWrite>,
+    /// The level of permission to grant the access token for managing repository environments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environments: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage the followers belonging to a
+    /// user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub followers: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage git SSH keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_ssh_keys: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage GPG keys belonging to a
+    /// user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpg_keys: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage interaction limits on a
+    /// repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interaction_limits: Option<ReadWrite>,
+    /// The level of permission to grant the access token for issues and related comments,
+    /// assignees, labels, and milestones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issues: Option<ReadWrite>,
+    /// The level of permission to grant the access token for organization teams and members.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage the merge queues for a
+    /// repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge_queues: Option<ReadWrite>,
+    /// The level of permission to grant the access token to search repositories, list
+    /// collaborators, and access repository metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage GitHub Actions variables of an
+    /// organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_actions_variables: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage access to an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_administration: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage announcement banners
+    /// for an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_announcement_banners: Option<ReadWrite>,
+    /// The level of permission to grant the access token for managing access to GitHub Copilot for
+    /// members of an organization with a Copilot Business subscription. This property is in beta
+    /// and is subject to change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_copilot_seat_management: Option<WriteOnly>,
+    /// The level of permission to grant the access token for custom organization roles management.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_custom_org_roles: Option<ReadWrite>,
+    /// The level of permission to grant the access token for custom property management.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_custom_properties: Option<ReadWriteAdmin>,
+    /// The level of permission to grant the access token for custom repository roles management.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_custom_roles: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view events triggered by an activity in
+    /// an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_events: Option<ReadOnly>,
+    /// The level of permission to grant the access token to manage the post-receive hooks for an
+    /// organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_hooks: Option<ReadWrite>,
+    /// The level of permission to grant the access token for organization packages published to
+    /// GitHub Packages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_packages: Option<ReadWrite>,
+    /// The level of permission to grant the access token for viewing and managing fine-grained
+    /// personal access tokens that have been approved by an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_personal_access_token_requests: Option<ReadWrite>,
+    /// The level of permission to grant the access token for viewing and managing fine-grained
+    /// personal access token requests to an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_personal_access_tokens: Option<ReadWrite>,
+    /// The level of permission to grant the access token for viewing an organization's plan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_plan: Option<ReadOnly>,
+    /// The level of permission to grant the access token to manage organization projects and
+    /// projects beta (where available).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_projects: Option<ReadWriteAdmin>,
+    /// The level of permission to grant the access token to manage organization secrets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_secrets: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage GitHub Actions
+    /// self-hosted runners available to an organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_self_hosted_runners: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage users blocked by the
+    /// organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_user_blocking: Option<ReadWrite>,
+    /// The level of permission to grant the access token for packages published to GitHub Packages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packages: Option<ReadWrite>,
+    /// The level of permission to grant the access token to retrieve Pages statuses, configuration,
+    /// and builds, as well as create new builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage the profile settings belonging
+    /// to a user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<WriteOnly>,
+    /// The level of permission to grant the access token for pull requests and related comments,
+    /// assignees, labels, milestones, and merges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_requests: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and edit custom properties for a
+    /// repository, when allowed by the property.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository_custom_properties: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage the post-receive hooks for a
+    /// repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository_hooks: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage repository projects, columns,
+    /// and cards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository_projects: Option<ReadWriteAdmin>,
+    /// The level of permission to grant the access token to view and manage secret scanning alerts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_scanning_alerts: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage repository secrets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<ReadWrite>,
+    /// The level of permission to grant the access token to view and manage security events like
+    /// code scanning alerts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_events: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage just a single file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_file: Option<ReadWrite>,
+    /// The level of permission to grant the access token to list and manage repositories a user is
+    /// starring.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starring: Option<ReadWrite>,
+    /// The level of permission to grant the access token for commit statuses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statuses: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage team discussions and related
+    /// comments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_discussions: Option<ReadWrite>,
+    /// The level of permission to grant the access token to manage Dependabot alerts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vulnerability_alerts: Option<ReadWrite>,
+    /// The level of permission to grant the access token to update GitHub Actions workflow files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workflows: Option<WriteOnly>,
+}
+
+permissions_impl! {
+    with_actions => actions: ReadWrite,
+    with_actions_variables => actions_variables: ReadWrite,
+    with_administration => administration: ReadWrite,
+    with_attestations => attestations: ReadWrite,
+    with_checks => checks: ReadWrite,
+    with_codespaces => codespaces: ReadWrite,
+    with_contents => contents: ReadWrite,
+    with_dependabot_secrets => dependabot_secrets: ReadWrite,
+    with_deployments => deployments: ReadWrite,
+    with_email_addresses => email_addresses: ReadWrite,
+    with_environments => environments: ReadWrite,
+    with_followers => followers: ReadWrite,
+    with_git_ssh_keys => git_ssh_keys: ReadWrite,
+    with_gpg_keys => gpg_keys: ReadWrite,
+    with_interaction_limits => interaction_limits: ReadWrite,
+    with_issues => issues: ReadWrite,
+    with_members => members: ReadWrite,
+    with_merge_queues => merge_queues: ReadWrite,
+    with_metadata => metadata: ReadWrite,
+    with_organization_actions_variables => organization_actions_variables: ReadWrite,
+    with_organization_administration => organization_administration: ReadWrite,
+    with_organization_announcement_banners => organization_announcement_banners: ReadWrite,
+    with_organization_copilot_seat_management => organization_copilot_seat_management: WriteOnly,
+    with_organization_custom_org_roles => organization_custom_org_roles: ReadWrite,
+    with_organization_custom_properties => organization_custom_properties: ReadWriteAdmin,
+    with_organization_custom_roles => organization_custom_roles: ReadWrite,
+    with_organization_events => organization_events: ReadOnly,
+    with_organization_hooks => organization_hooks: ReadWrite,
+    with_organization_packages => organization_packages: ReadWrite,
+    with_organization_personal_access_token_requests => organization_personal_access_token_requests: ReadWrite,
+    with_organization_personal_access_tokens => organization_personal_access_tokens: ReadWrite,
+    with_organization_plan => organization_plan: ReadOnly,
+    with_organization_projects => organization_projects: ReadWriteAdmin,
+    with_organization_secrets => organization_secrets: ReadWrite,
+    with_organization_self_hosted_runners => organization_self_hosted_runners: ReadWrite,
+    with_organization_user_blocking => organization_user_blocking: ReadWrite,
+    with_packages => packages: ReadWrite,
+    with_pages => pages: ReadWrite,
+    with_profile => profile: WriteOnly,
+    with_pull_requests => pull_requests: ReadWrite,
+    with_repository_custom_properties => repository_custom_properties: ReadWrite,
+    with_repository_hooks => repository_hooks: ReadWrite,
+    with_repository_projects => repository_projects: ReadWriteAdmin,
+    with_secret_scanning_alerts => secret_scanning_alerts: ReadWrite,
+    with_secrets => secrets: ReadWrite,
+    with_security_events => security_events: ReadWrite,
+    with_single_file => single_file: ReadWrite,
+    with_starring => starring: ReadWrite,
+    with_statuses => statuses: ReadWrite,
+    with_team_discussions => team_discussions: ReadWrite,
+    with_vulnerability_alerts => vulnerability_alerts: ReadWrite,
+    with_workflows => workflows: WriteOnly,
+}
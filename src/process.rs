@@ -0,0 +1,47 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::collections::HashMap;
+
+use crate::{GitHubAuthenticatorError, GitHubHost, RefreshingGitHubInstallationAuthenticator};
+
+impl RefreshingGitHubInstallationAuthenticator {
+    /// The environment variables that `gh`, `git` based tooling and most other clients read a
+    /// token from, set to a current token: `GITHUB_TOKEN` and `GH_TOKEN`. For apps on a deployment
+    /// other than github.com, `GH_HOST` and `GH_ENTERPRISE_TOKEN` are set as well so that `gh`
+    /// talks to the right host.
+    pub async fn env_for_subprocess(&self) -> Result<HashMap<&'static str, String>, GitHubAuthenticatorError> {
+        let token = self.access_token().await?;
+
+        let mut env = HashMap::new();
+        env.insert("GITHUB_TOKEN", token.clone());
+        env.insert("GH_TOKEN", token.clone());
+
+        match self.host() {
+            GitHubHost::Dotcom => {}
+            host => {
+                let endpoint = host.web_endpoint();
+                let (_, name) = endpoint.split_once("://").unwrap_or(("https", &endpoint));
+
+                env.insert("GH_HOST", name.trim_end_matches('/').to_string());
+                env.insert("GH_ENTERPRISE_TOKEN", token);
+            }
+        }
+
+        Ok(env)
+    }
+
+    /// Spawn a command with the variables of [`env_for_subprocess`](Self::env_for_subprocess)
+    /// added to its environment. The token is fetched right before spawning, so that the
+    /// subprocess starts out with an unexpired token.
+    #[cfg(feature = "process")]
+    pub async fn spawn_with_token(&self, command: &mut tokio::process::Command) -> Result<tokio::process::Child, GitHubAuthenticatorError> {
+        command
+            .envs(self.env_for_subprocess().await?)
+            .spawn()
+            .map_err(GitHubAuthenticatorError::FailedToSpawnProcess)
+    }
+}
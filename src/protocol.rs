@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use http::{header::{AUTHORIZATION, CONTENT_TYPE, USER_AGENT}, Method, Request, Response, StatusCode};
+
+use crate::{app::{DEFAULT_API_VERSION, X_GITHUB_API_VERSION}, token::{bearer_authorization, decode_access_token}, AccessToken, GitHubAuthenticatorError, GitHubHost, RequestFailure, TokenRequest};
+
+/// Build the request that exchanges an app JWT for an access token of an installation on
+/// github.com, without sending it. Together with [`parse_token_response`], this allows tokens to
+/// be requested via HTTP clients that are not supported by [`crate::HttpTransport`]. See
+/// [`build_token_request_for`] for GitHub Enterprise Server.
+///
+/// ```
+/// # use github_app_authenticator::{build_token_request, parse_token_response, TokenRequest};
+/// # fn example(jwt: &str) -> Result<(), github_app_authenticator::GitHubAuthenticatorError> {
+/// let request = build_token_request(12345, &TokenRequest::default(), jwt)?;
+/// // Send the request with any HTTP client, then parse its response
+/// # let (status, body) = (http::StatusCode::CREATED, br#"{"token":"ghs_example","expires_at":"2023-01-01T00:00:00Z"}"#);
+/// let token = parse_token_response(status, body)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn build_token_request<J>(installation_id: u32, request: &TokenRequest, jwt: J) -> Result<Request<Vec<u8>>, GitHubAuthenticatorError> where J: AsRef<str> {
+    build_token_request_for(&GitHubHost::Dotcom.api_endpoint(), installation_id, request, jwt)
+}
+
+/// Build the request that exchanges an app JWT for an access token of an installation, for the
+/// API at `base_endpoint`, e.g. `https://github.example.com/api/v3`.
+pub fn build_token_request_for<J>(base_endpoint: &str, installation_id: u32, request: &TokenRequest, jwt: J) -> Result<Request<Vec<u8>>, GitHubAuthenticatorError> where J: AsRef<str> {
+    let body = serde_json::to_vec(request).map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+    Request::builder()
+        .method(Method::POST)
+        .uri(token_endpoint(base_endpoint, installation_id))
+        .header(USER_AGENT, concat!("github-app-authenticator/", env!("CARGO_PKG_VERSION")))
+        .header(AUTHORIZATION, bearer_authorization(jwt.as_ref())?)
+        .header(CONTENT_TYPE, "application/json")
+        .header(X_GITHUB_API_VERSION, DEFAULT_API_VERSION)
+        .body(body)
+        .map_err(|err| GitHubAuthenticatorError::Transport(Box::new(err)))
+}
+
+/// Parse the response to a token request that was built by [`build_token_request`]. Failures are
+/// classified like those of token requests sent by this crate, except that suspended and missing
+/// installations are reported as [`GitHubAuthenticatorError::InstallationRequestFailed`], as the
+/// response alone does not tell which installation it belongs to. Rate limits are only detected
+/// from the message of the response, and the rate limit of the returned token is not set, as both
+/// are reported via headers.
+pub fn parse_token_response(status: StatusCode, body: &[u8]) -> Result<AccessToken, GitHubAuthenticatorError> {
+    if status == StatusCode::CREATED {
+        decode_access_token(body)
+    } else {
+        let mut response = Response::new(body.to_vec());
+        *response.status_mut() = status;
+
+        Err(GitHubAuthenticatorError::token_request_failed(None, None, RequestFailure::from_response(&response)))
+    }
+}
+
+pub(crate) fn token_endpoint(base_endpoint: &str, installation_id: u32) -> String {
+    format!("{}/app/installations/{}/access_tokens", base_endpoint, installation_id)
+}
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator};
+
+/// A source of access tokens for a fixed installation and set of permissions. Code that only needs
+/// a token can accept a `TokenProvider` (for instance as `Arc<dyn TokenProvider>`) instead of a
+/// [`RefreshingGitHubInstallationAuthenticator`], so that it can be tested with a
+/// [`StaticTokenProvider`] rather than a private key and a mock server. On wasm targets the
+/// returned future is not required to be `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait TokenProvider: Send + Sync {
+    /// Get an access token that is valid at the time of the call.
+    async fn access_token(&self) -> Result<String, GitHubAuthenticatorError>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl TokenProvider for RefreshingGitHubInstallationAuthenticator {
+    async fn access_token(&self) -> Result<String, GitHubAuthenticatorError> {
+        RefreshingGitHubInstallationAuthenticator::access_token(self).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T> TokenProvider for Arc<T> where T: TokenProvider + ?Sized {
+    async fn access_token(&self) -> Result<String, GitHubAuthenticatorError> {
+        self.as_ref().access_token().await
+    }
+}
+
+/// A provider that always returns the same token, for tests and for environments where a token is
+/// issued externally, such as the `GITHUB_TOKEN` of GitHub Actions. The token is omitted from
+/// debug output.
+#[derive(Clone)]
+pub struct StaticTokenProvider {
+    token: String,
+}
+
+impl StaticTokenProvider {
+    /// Create a provider that returns `token`.
+    pub fn new<T>(token: T) -> Self where T: Into<String> {
+        Self { token: token.into() }
+    }
+
+    /// Create a provider that returns the token in the environment variable `name`, for instance
+    /// `GITHUB_TOKEN`.
+    pub fn from_env(name: &str) -> Result<Self, GitHubAuthenticatorError> {
+        std::env::var(name)
+            .map(Self::new)
+            .map_err(|err| GitHubAuthenticatorError::InvalidConfiguration(format!("failed to read token from {}: {}", name, err)))
+    }
+}
+
+impl Debug for StaticTokenProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticTokenProvider").finish()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl TokenProvider for StaticTokenProvider {
+    async fn access_token(&self) -> Result<String, GitHubAuthenticatorError> {
+        Ok(self.token.clone())
+    }
+}
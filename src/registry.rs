@@ -0,0 +1,73 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::collections::BTreeMap;
+
+use crate::{GitHubAppAuthenticator, GitHubHost, InstallationManager};
+
+/// Holds the apps of a service that acts as several GitHub Apps, for instance one app per
+/// environment or the same app registered on both github.com and a GitHub Enterprise Server
+/// instance. Each app is registered under a name along with the installation manager that hands
+/// out its installation authenticators.
+///
+/// Apps can be looked up by name, or by app id and host, for instance to route a webhook to the
+/// app that it was delivered for.
+#[derive(Clone, Debug, Default)]
+pub struct AppRegistry {
+    apps: BTreeMap<String, InstallationManager>,
+}
+
+impl AppRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the app of an installation manager under a name, replacing any app that was
+    /// previously registered under the same name.
+    pub fn with_app<N>(&mut self, name: N, manager: InstallationManager) -> &mut Self where N: Into<String> {
+        self.apps.insert(name.into(), manager);
+        self
+    }
+
+    /// Remove the app registered under a name, returning its installation manager.
+    pub fn remove(&mut self, name: &str) -> Option<InstallationManager> {
+        self.apps.remove(name)
+    }
+
+    /// Get the installation manager of the app registered under a name.
+    pub fn get(&self, name: &str) -> Option<&InstallationManager> {
+        self.apps.get(name)
+    }
+
+    /// Get the installation manager of the app with the given id on the given host. App ids are
+    /// only unique within a single GitHub deployment.
+    pub fn find(&self, app_id: u32, host: &GitHubHost) -> Option<&InstallationManager> {
+        self.apps
+            .values()
+            .find(|manager| manager.app().app_id() == app_id && &manager.app().host() == host)
+    }
+
+    /// Get the app registered under a name.
+    pub fn app(&self, name: &str) -> Option<&GitHubAppAuthenticator> {
+        self.get(name).map(InstallationManager::app)
+    }
+
+    /// Iterate over the registered apps by name, in order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &InstallationManager)> {
+        self.apps.iter().map(|(name, manager)| (name.as_str(), manager))
+    }
+
+    /// The number of registered apps.
+    pub fn len(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Check whether no apps are registered.
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty()
+    }
+}
@@ -0,0 +1,129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, time::Duration};
+
+use crate::GitHubAuthenticatorError;
+
+/// Decides whether a failed installation token request is sent again. A policy configured via
+/// [`GitHubAppAuthenticator::with_retry_policy`](crate::GitHubAppAuthenticator::with_retry_policy)
+/// applies to the token requests of all installation authenticators of the app.
+///
+/// Policies are implemented for closures, which makes for concise policies in tests:
+///
+/// ```
+/// # use github_app_authenticator::{GitHubAuthenticatorError, RetryDecision};
+/// # use std::time::Duration;
+/// // Retry failures that may succeed later up to three times without waiting in between
+/// let policy = |attempt: u32, err: &GitHubAuthenticatorError, _elapsed: Duration| {
+///     if attempt < 3 && err.is_retryable() {
+///         RetryDecision::Retry(Duration::ZERO)
+///     } else {
+///         RetryDecision::DoNotRetry
+///     }
+/// };
+/// ```
+pub trait RetryPolicy: Send + Sync {
+    /// Decide whether to send a request again after its `attempt`th attempt failed with `error`,
+    /// `elapsed` after the first attempt was sent. Attempts are counted from 1. The status that
+    /// GitHub responded with, if any, is available via [`GitHubAuthenticatorError::status`].
+    fn retry(&self, attempt: u32, error: &GitHubAuthenticatorError, elapsed: Duration) -> RetryDecision;
+}
+
+impl<F> RetryPolicy for F
+where
+    F: Fn(u32, &GitHubAuthenticatorError, Duration) -> RetryDecision + Send + Sync,
+{
+    fn retry(&self, attempt: u32, error: &GitHubAuthenticatorError, elapsed: Duration) -> RetryDecision {
+        self(attempt, error, elapsed)
+    }
+}
+
+/// The decision of a [`RetryPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Send the request again after waiting for the given delay.
+    Retry(Duration),
+    /// Fail with the error of the last attempt.
+    DoNotRetry,
+}
+
+/// Retries failures that may succeed later, see [`GitHubAuthenticatorError::is_retryable`], with
+/// exponentially increasing delays. Delays are randomized to keep many clients from retrying in
+/// lockstep, and are never shorter than the delay that GitHub asked for when rate limiting a
+/// request. Failures due to an open [`CircuitBreaker`](crate::CircuitBreaker) are not retried.
+///
+/// By default up to 3 retries are made within 30 seconds, starting with a delay of 500ms.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_retries: u32,
+    max_elapsed: Duration,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            max_retries: 3,
+            max_elapsed: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// Configure the delay before the first retry. The delay doubles with every further retry.
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Configure the longest delay between two attempts.
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Configure the number of retries after which the last failure is returned.
+    pub fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Configure the time after the first attempt after which no further retries are made.
+    pub fn with_max_elapsed(mut self, elapsed: Duration) -> Self {
+        self.max_elapsed = elapsed;
+        self
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn retry(&self, attempt: u32, error: &GitHubAuthenticatorError, elapsed: Duration) -> RetryDecision {
+        if attempt > self.max_retries
+            || !error.is_retryable()
+            || matches!(error, GitHubAuthenticatorError::CircuitOpen)
+        {
+            return RetryDecision::DoNotRetry;
+        }
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        // Wait for somewhere between half of the backoff and the full backoff
+        let jitter = backoff.mul_f64(RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64 / 2.0);
+        let delay = (backoff - jitter).max(error.retry_after().unwrap_or_default());
+
+        if elapsed + delay > self.max_elapsed {
+            RetryDecision::DoNotRetry
+        } else {
+            RetryDecision::Retry(delay)
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, Timestamp, TimeDelta};
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use tokio::sync::Notify;
+
+use crate::logging::log_at;
+use crate::token::MAX_MIN_VALIDITY;
+
+use crate::{GitHubAppAuthenticator, GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator, Shutdown, TokenRequest, TokenStats};
+
+/// Keeps the tokens of many installations of an app alive by refreshing them ahead of their
+/// expiry, for apps that need a valid token for every installation at all times. Refreshes are
+/// performed earliest expiry first, and are capped at a maximum rate so that refreshing thousands
+/// of installations at once, for instance at startup, is spread out over time.
+///
+/// Refreshes are only performed while [`Self::run`] or [`Self::run_until`] is being polled. Cloning is cheap, and all
+/// clones share the same installations.
+#[derive(Clone, Debug)]
+pub struct RefreshScheduler {
+    app: GitHubAppAuthenticator,
+    request: Arc<TokenRequest>,
+    min_validity: TimeDelta,
+    retry_interval: std::time::Duration,
+    max_not_found: u32,
+    interval: std::time::Duration,
+    entries: Arc<Mutex<HashMap<u32, Entry>>>,
+    // Signalled when installations are added, as they may be due before the installation that the
+    // scheduler is currently waiting on
+    added: Arc<Notify>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+    next_refresh: Timestamp,
+    // The number of refreshes in a row that GitHub reported the installation as not found
+    not_found: u32,
+}
+
+/// The refresh status of an installation kept alive by a [`RefreshScheduler`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RefreshStatus {
+    /// The time at which the token of the installation is due to be refreshed next.
+    pub next_refresh: Timestamp,
+    /// The tokens fetched for the installation so far.
+    pub stats: TokenStats,
+}
+
+impl GitHubAppAuthenticator {
+    /// Create a scheduler that keeps tokens for the given request alive across many installations
+    /// of this app.
+    pub fn refresh_scheduler(&self, request: TokenRequest) -> RefreshScheduler {
+        RefreshScheduler {
+            app: self.clone(),
+            request: Arc::new(request),
+            min_validity: time::minutes(15),
+            retry_interval: std::time::Duration::from_secs(30),
+            max_not_found: 3,
+            interval: std::time::Duration::from_millis(100),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            added: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl RefreshScheduler {
+    /// Configure how long tokens remain valid at the least. Tokens are refreshed before their
+    /// remaining lifetime falls below this duration. Defaults to 15 minutes.
+    ///
+    /// GitHub issues tokens that are valid for an hour, so durations are capped at 45 minutes to
+    /// leave some time between refreshes.
+    pub fn with_min_validity(mut self, min_validity: TimeDelta) -> Self {
+        self.min_validity = min_validity.clamp(time::zero(), MAX_MIN_VALIDITY);
+        self
+    }
+
+    /// Configure how long to wait before trying again after failing to refresh the token of an
+    /// installation. Defaults to 30 seconds.
+    pub fn with_retry_interval(mut self, interval: std::time::Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Configure how many refreshes in a row GitHub may report an installation as not found before
+    /// it is removed from the scheduler. Installations are not removed on the first such report,
+    /// as GitHub may briefly report newly created installations as not found. Defaults to 3.
+    pub fn with_max_not_found(mut self, max_not_found: u32) -> Self {
+        self.max_not_found = max_not_found.max(1);
+        self
+    }
+
+    /// Configure the maximum number of refreshes performed per second across all installations.
+    /// Defaults to 10.
+    pub fn with_max_refreshes_per_second(mut self, max_per_second: u32) -> Self {
+        self.interval = std::time::Duration::from_secs(1) / max_per_second.max(1);
+        self
+    }
+
+    /// Keep the token of an installation alive, returning the authenticator that holds it. Newly
+    /// added installations are due for a refresh immediately.
+    pub fn add(&self, installation_id: u32) -> RefreshingGitHubInstallationAuthenticator {
+        let mut entries = self.entries.lock().unwrap();
+
+        let entry = entries.entry(installation_id).or_insert_with(|| {
+            self.added.notify_one();
+
+            Entry {
+                authenticator: self
+                    .app
+                    .installation_authenticator(installation_id)
+                    .into_refreshing(self.request.as_ref().clone()),
+                next_refresh: time::now(),
+                not_found: 0,
+            }
+        });
+
+        entry.authenticator.clone()
+    }
+
+    /// Stop keeping the token of an installation alive, returning its authenticator.
+    pub fn remove(&self, installation_id: u32) -> Option<RefreshingGitHubInstallationAuthenticator> {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&installation_id)
+            .map(|entry| entry.authenticator)
+    }
+
+    /// Get the authenticator of an installation, if its token is kept alive.
+    pub fn get(&self, installation_id: u32) -> Option<RefreshingGitHubInstallationAuthenticator> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&installation_id)
+            .map(|entry| entry.authenticator.clone())
+    }
+
+    /// The refresh status of an installation, if its token is kept alive.
+    pub fn status(&self, installation_id: u32) -> Option<RefreshStatus> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&installation_id)
+            .map(Entry::status)
+    }
+
+    /// The refresh status of every installation whose token is kept alive, by installation id.
+    pub fn statuses(&self) -> HashMap<u32, RefreshStatus> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(installation_id, entry)| (*installation_id, entry.status()))
+            .collect()
+    }
+
+    /// The number of installations whose tokens are kept alive.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Check whether no installations are kept alive.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Refresh tokens as they become due until the returned future is dropped. Installations that
+    /// GitHub reports as gone, or as not found too many times in a row, are removed from the
+    /// scheduler.
+    pub async fn run(&self) {
+        self.run_until(&Shutdown::new()).await
+    }
+
+    /// Refresh tokens as they become due until shutdown is signalled, see [`Self::run`]. A
+    /// refresh that is in flight when shutdown is signalled is completed. The tokens of all
+    /// installations are revoked before returning if the shutdown is configured to revoke tokens.
+    pub async fn run_until(&self, shutdown: &Shutdown) {
+        while !shutdown.is_signalled() {
+            let next = self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .min_by_key(|(_, entry)| entry.next_refresh)
+                .map(|(installation_id, entry)| (*installation_id, entry.clone()));
+
+            let (installation_id, entry) = match next {
+                Some(next) => next,
+                None => {
+                    shutdown.unless_signalled(self.added.notified()).await;
+                    continue;
+                }
+            };
+
+            let delay = time::to_std(time::between(entry.next_refresh, time::now()));
+
+            if !delay.is_zero() {
+                let sleep = Box::pin(tokio::time::sleep(delay));
+                let added = Box::pin(self.added.notified());
+
+                // Start over once installations are added, as they are due immediately, or once
+                // shutdown is signalled
+                match shutdown.unless_signalled(futures_util::future::select(sleep, added)).await {
+                    Some(futures_util::future::Either::Left(_)) => {}
+                    _ => continue,
+                }
+            }
+
+            self.refresh(installation_id, &entry.authenticator).await;
+            shutdown.unless_signalled(tokio::time::sleep(self.interval)).await;
+        }
+
+        if shutdown.revokes_tokens() {
+            let authenticators = self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(installation_id, entry)| (*installation_id, entry.authenticator.clone()))
+                .collect::<Vec<_>>();
+
+            for (installation_id, authenticator) in authenticators {
+                if let Err(err) = authenticator.revoke().await {
+                    let logging = self.app.logging();
+                    log_at!(logging.request_failures(), ?err, installation_id = %logging.id(installation_id), "Failed to revoke installation access token on shutdown");
+                }
+            }
+        }
+    }
+
+    async fn refresh(&self, installation_id: u32, authenticator: &RefreshingGitHubInstallationAuthenticator) {
+        // The refreshing authenticator considers tokens expired a few minutes before GitHub does,
+        // so a token that is due always results in a new token
+        let result = authenticator.access_token_detailed_valid_for(self.min_validity).await;
+
+        let mut entries = self.entries.lock().unwrap();
+
+        // The installation may have been removed while its token was being refreshed
+        let entry = match entries.get_mut(&installation_id) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let logging = self.app.logging();
+
+        match result {
+            Ok(token) => {
+                entry.not_found = 0;
+                entry.next_refresh = token.expires_at - self.min_validity;
+            }
+            Err(GitHubAuthenticatorError::InstallationGone(_)) => {
+                log_at!(logging.request_failures(), installation_id = %logging.id(installation_id), "Removing installation that has been marked as gone from the refresh scheduler");
+
+                entries.remove(&installation_id);
+            }
+            Err(err) => {
+                entry.not_found = match err {
+                    GitHubAuthenticatorError::InstallationNotFound(_) => entry.not_found + 1,
+                    _ => 0,
+                };
+
+                if entry.not_found >= self.max_not_found {
+                    log_at!(logging.request_failures(), installation_id = %logging.id(installation_id), not_found = entry.not_found, "Removing installation that was not found from the refresh scheduler");
+
+                    entries.remove(&installation_id);
+                } else {
+                    log_at!(logging.request_failures(), ?err, installation_id = %logging.id(installation_id), "Failed to refresh installation access token");
+
+                    entry.next_refresh = time::now() + time::from_std(self.retry_interval);
+                }
+            }
+        }
+    }
+}
+
+impl Entry {
+    fn status(&self) -> RefreshStatus {
+        RefreshStatus {
+            next_refresh: self.next_refresh,
+            stats: self.authenticator.stats(),
+        }
+    }
+}
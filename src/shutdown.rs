@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use tokio::sync::Notify;
+
+/// Signals background components to stop, for instance during a rolling deploy. Components that
+/// are run until shutdown, such as
+/// [`RefreshScheduler::run_until`](crate::RefreshScheduler::run_until) and
+/// [`TokenFileWriter::run_until`](crate::TokenFileWriter::run_until), finish any request that is
+/// in flight when shutdown is signalled and then return. Cloning is cheap, and signalling any
+/// clone signals all of them.
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+    signalled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+    revoke_tokens: bool,
+}
+
+impl Shutdown {
+    /// Create a handle that has not been signalled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configure whether components revoke the tokens that they hold before returning, so that
+    /// tokens do not outlive the process that used them. Defaults to false.
+    pub fn with_revoke_tokens(mut self, revoke_tokens: bool) -> Self {
+        self.revoke_tokens = revoke_tokens;
+        self
+    }
+
+    /// Check whether components revoke the tokens that they hold before returning.
+    pub fn revokes_tokens(&self) -> bool {
+        self.revoke_tokens
+    }
+
+    /// Signal all components run until this shutdown to stop.
+    pub fn signal(&self) {
+        self.signalled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Check whether shutdown has been signalled.
+    pub fn is_signalled(&self) -> bool {
+        self.signalled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until shutdown is signalled.
+    pub async fn signalled(&self) {
+        loop {
+            // Created before checking the flag, so that a signal in between is not missed
+            let notified = self.notify.notified();
+
+            if self.is_signalled() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    // Run a future unless shutdown is signalled first, in which case the future is dropped
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn unless_signalled<F>(&self, future: F) -> Option<F::Output> where F: std::future::Future {
+        let future = Box::pin(future);
+        let signalled = Box::pin(self.signalled());
+
+        match futures_util::future::select(future, signalled).await {
+            futures_util::future::Either::Left((output, _)) => Some(output),
+            futures_util::future::Either::Right(_) => None,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use base64::Engine;
+use crate::time::{self, TimeDelta};
+use http::{HeaderValue, Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex}};
+
+use crate::{GitHubAppAuthenticator, GitHubAuthenticatorError, HttpTransport, TokenRequest};
+
+/// A transport that answers token requests locally instead of sending them to GitHub, for local
+/// development and simulations. Tokens are derived deterministically from the installation, the
+/// request and the number of tokens issued for them so far, and requests are never sent over the
+/// network. Requests to endpoints other than the token and revocation endpoints fail with a 404.
+///
+/// Cloning is cheap, and all clones share the tokens issued so far.
+///
+/// ```
+/// # use github_app_authenticator::{GitHubAppAuthenticator, SimulatedTransport, TimeDelta, TokenRequest};
+/// # async fn example() {
+/// # #[cfg(feature = "chrono")]
+/// let lifetime = TimeDelta::minutes(10);
+/// # #[cfg(not(feature = "chrono"))]
+/// # let lifetime = TimeDelta::from_mins(10);
+/// let transport = SimulatedTransport::new().with_token_lifetime(lifetime);
+/// let app = GitHubAppAuthenticator::simulated(12345, transport.clone());
+///
+/// let token = app
+///     .installation_authenticator(1)
+///     .access_token(&TokenRequest::default())
+///     .await
+///     .unwrap();
+/// assert_eq!(1, transport.tokens_issued());
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SimulatedTransport {
+    token_lifetime: TimeDelta,
+    // The installations that exist, or None if every installation exists
+    installations: Option<HashSet<u32>>,
+    state: Arc<Mutex<SimulationState>>,
+}
+
+#[derive(Debug, Default)]
+struct SimulationState {
+    // The number of tokens issued by installation and request digest
+    issued: HashMap<(u32, String), u64>,
+    revoked: u64,
+}
+
+impl Default for SimulatedTransport {
+    fn default() -> Self {
+        Self {
+            token_lifetime: time::hours(1),
+            installations: None,
+            state: Arc::new(Mutex::new(SimulationState::default())),
+        }
+    }
+}
+
+impl SimulatedTransport {
+    /// Create a transport that issues tokens for every installation that are valid for an hour,
+    /// like GitHub does.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configure how long issued tokens are valid for. Short lifetimes are useful for exercising
+    /// refreshes.
+    pub fn with_token_lifetime(mut self, lifetime: TimeDelta) -> Self {
+        self.token_lifetime = lifetime;
+        self
+    }
+
+    /// Only issue tokens for the given installations. Token requests for any other installation
+    /// fail as if the installation did not exist.
+    pub fn with_installations<I>(mut self, installation_ids: I) -> Self where I: IntoIterator<Item = u32> {
+        self.installations = Some(installation_ids.into_iter().collect());
+        self
+    }
+
+    /// The number of tokens issued so far.
+    pub fn tokens_issued(&self) -> u64 {
+        self.state.lock().unwrap().issued.values().sum()
+    }
+
+    /// The number of tokens revoked so far.
+    pub fn tokens_revoked(&self) -> u64 {
+        self.state.lock().unwrap().revoked
+    }
+
+    fn issue(&self, installation_id: u32, body: &[u8]) -> Response<Vec<u8>> {
+        if self.installations.as_ref().map(|installations| !installations.contains(&installation_id)).unwrap_or(false) {
+            return json_response(StatusCode::NOT_FOUND, serde_json::json!({ "message": "Not Found" }));
+        }
+
+        let request = serde_json::from_slice::<TokenRequest>(body).unwrap_or_default();
+        let digest = hex::encode(&Sha256::digest(body)[..4]);
+
+        let sequence = {
+            let mut state = self.state.lock().unwrap();
+            let issued = state.issued.entry((installation_id, digest.clone())).or_default();
+            *issued += 1;
+            *issued
+        };
+
+        let selected = request.repositories.is_some() || request.repository_ids.is_some();
+
+        json_response(StatusCode::CREATED, serde_json::json!({
+            "token": format!("ghs_simulated_{}_{}_{}", installation_id, digest, sequence),
+            "expires_at": time::now() + self.token_lifetime,
+            "permissions": request.permissions,
+            "repository_selection": if selected { "selected" } else { "all" },
+        }))
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Vec<u8>> {
+    let mut response = Response::new(serde_json::to_vec(&body).unwrap_or_default());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    response
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpTransport for SimulatedTransport {
+    async fn send(
+        &self,
+        request: Request<Vec<u8>>,
+        _timeout: std::time::Duration,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        // Endpoints are matched by their trailing segments, so that any base uri works
+        let segments = request.uri().path().trim_end_matches('/').rsplit('/').take(4).collect::<Vec<_>>();
+
+        let response = match (request.method(), segments.as_slice()) {
+            (&Method::POST, ["access_tokens", installation_id, "installations", "app"]) => match installation_id.parse() {
+                Ok(installation_id) => self.issue(installation_id, request.body()),
+                Err(_) => json_response(StatusCode::NOT_FOUND, serde_json::json!({ "message": "Not Found" })),
+            },
+            (&Method::DELETE, ["token", "installation", ..]) => {
+                self.state.lock().unwrap().revoked += 1;
+                json_response(StatusCode::NO_CONTENT, serde_json::Value::Null)
+            }
+            _ => json_response(StatusCode::NOT_FOUND, serde_json::json!({ "message": "Not simulated" })),
+        };
+
+        Ok(response)
+    }
+}
+
+impl GitHubAppAuthenticator {
+    /// Create an app authenticator that never contacts GitHub, with tokens issued by `transport`
+    /// instead. No private key is needed, and the JWTs that authenticate requests to app
+    /// endpoints are left unsigned, so the authenticator must not be configured with another
+    /// transport. Installation authenticators created from it go through the same caching and
+    /// refresh logic as they would against GitHub.
+    pub fn simulated(app_id: u32, transport: SimulatedTransport) -> Self {
+        let mut app = GitHubAppAuthenticator::new(
+            app_id,
+            Vec::new(),
+            HeaderValue::from_static("github-app-authenticator-simulated"),
+        );
+        app.with_transport(transport);
+        app.simulated = true;
+
+        app
+    }
+}
+
+// A JWT in the usual form, but without a signature, for simulated apps that do not have a key
+pub(crate) fn unsigned_jwt<T>(claims: &T) -> Result<String, GitHubAuthenticatorError> where T: serde::Serialize {
+    let encode = |value: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value);
+    let claims = serde_json::to_vec(claims).map_err(GitHubAuthenticatorError::FailedToEncodeRequest)?;
+
+    Ok(format!("{}.{}.", encode(br#"{"alg":"none","typ":"JWT"}"#), encode(&claims)))
+}
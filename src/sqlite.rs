@@ -0,0 +1,198 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use crate::time;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use std::{path::Path, sync::{Arc, Mutex}};
+
+use crate::{AccessToken, GitHubAuthenticatorError, TokenStore};
+
+// Applied in order to bring a database up to date, with the number of applied migrations recorded
+// in the user_version of the database. Migrations must never be changed once released
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE tokens (
+        key TEXT PRIMARY KEY NOT NULL,
+        token TEXT NOT NULL,
+        expires_at TEXT NOT NULL
+    );
+    CREATE TABLE leases (
+        key TEXT PRIMARY KEY NOT NULL,
+        holder TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );",
+];
+
+/// A [`TokenStore`] that keeps tokens in a local SQLite database, for daemons that restart often
+/// and should pick up their previous tokens instead of fetching new ones, or for several processes
+/// on a single host that share tokens. The schema of the database is created and migrated when it
+/// is opened.
+///
+/// Tokens are stored unencrypted, so the database must be protected like any other credential.
+/// Cloning is cheap, and all clones share the same connection.
+#[derive(Clone)]
+pub struct SqliteTokenStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl std::fmt::Debug for SqliteTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteTokenStore").finish()
+    }
+}
+
+impl SqliteTokenStore {
+    /// Open the database at `path`, creating it if it does not exist.
+    pub fn open<P>(path: P) -> Result<Self, GitHubAuthenticatorError> where P: AsRef<Path> {
+        Self::from_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    /// Open a database that only lives as long as the store, which is mostly useful for tests.
+    pub fn open_in_memory() -> Result<Self, GitHubAuthenticatorError> {
+        Self::from_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, GitHubAuthenticatorError> {
+        // Other processes may be writing to the database at the same time
+        connection
+            .busy_timeout(std::time::Duration::from_secs(5))
+            .map_err(store_error)?;
+
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    // Run a query on the blocking thread pool, as SQLite performs blocking file IO
+    async fn with_connection<F, T>(&self, f: F) -> Result<T, GitHubAuthenticatorError>
+    where
+        F: FnOnce(&Connection) -> Result<T, GitHubAuthenticatorError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || f(&connection.lock().unwrap()))
+            .await
+            .map_err(|err| GitHubAuthenticatorError::FailedToAccessTokenStore(Box::new(err)))?
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), GitHubAuthenticatorError> {
+    // Take the write lock up front, so that processes opening the database at the same time do not
+    // both apply the same migrations
+    let transaction = connection
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(store_error)?;
+    let version: usize = transaction
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(store_error)?;
+
+    if version > MIGRATIONS.len() {
+        return Err(GitHubAuthenticatorError::FailedToAccessTokenStore(
+            format!("database schema version {} is newer than the supported version {}", version, MIGRATIONS.len()).into(),
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        transaction.execute_batch(migration).map_err(store_error)?;
+        transaction
+            .pragma_update(None, "user_version", index + 1)
+            .map_err(store_error)?;
+    }
+
+    transaction.commit().map_err(store_error)
+}
+
+fn store_error(err: rusqlite::Error) -> GitHubAuthenticatorError {
+    GitHubAuthenticatorError::FailedToAccessTokenStore(Box::new(err))
+}
+
+#[async_trait]
+impl TokenStore for SqliteTokenStore {
+    async fn get(&self, key: &str) -> Result<Option<AccessToken>, GitHubAuthenticatorError> {
+        let key = key.to_string();
+
+        let token = self
+            .with_connection(move |connection| {
+                connection
+                    .query_row("SELECT token FROM tokens WHERE key = ?1", [&key], |row| row.get::<_, String>(0))
+                    .optional()
+                    .map_err(store_error)
+            })
+            .await?;
+
+        token
+            .map(|token| serde_json::from_str(&token).map_err(|err| GitHubAuthenticatorError::FailedToAccessTokenStore(Box::new(err))))
+            .transpose()
+    }
+
+    async fn put(&self, key: &str, token: &AccessToken) -> Result<(), GitHubAuthenticatorError> {
+        let key = key.to_string();
+        let expires_at = time::format_rfc3339(token.expires_at);
+        let token = serde_json::to_string(token).map_err(|err| GitHubAuthenticatorError::FailedToAccessTokenStore(Box::new(err)))?;
+
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO tokens (key, token, expires_at) VALUES (?1, ?2, ?3)
+                    ON CONFLICT (key) DO UPDATE SET token = excluded.token, expires_at = excluded.expires_at",
+                    [&key, &token, &expires_at],
+                )
+                .map(|_| ())
+                .map_err(store_error)
+        })
+        .await
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), GitHubAuthenticatorError> {
+        let key = key.to_string();
+
+        self.with_connection(move |connection| {
+            connection
+                .execute("DELETE FROM tokens WHERE key = ?1", [&key])
+                .map(|_| ())
+                .map_err(store_error)
+        })
+        .await
+    }
+
+    async fn try_acquire_lease(&self, key: &str, holder: &str, ttl: std::time::Duration) -> Result<bool, GitHubAuthenticatorError> {
+        let key = key.to_string();
+        let holder = holder.to_string();
+        let now = time::unix_millis(time::now());
+        let expires_at = now.saturating_add(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX));
+
+        // An existing lease is only taken over if it is held by the same holder or has expired.
+        // Otherwise the upsert changes no rows
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO leases (key, holder, expires_at) VALUES (?1, ?2, ?3)
+                    ON CONFLICT (key) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at
+                    WHERE leases.holder = excluded.holder OR leases.expires_at <= ?4",
+                    rusqlite::params![key, holder, expires_at, now],
+                )
+                .map(|changed| changed > 0)
+                .map_err(store_error)
+        })
+        .await
+    }
+
+    async fn release_lease(&self, key: &str, holder: &str) -> Result<(), GitHubAuthenticatorError> {
+        let key = key.to_string();
+        let holder = holder.to_string();
+
+        self.with_connection(move |connection| {
+            connection
+                .execute("DELETE FROM leases WHERE key = ?1 AND holder = ?2", [&key, &holder])
+                .map(|_| ())
+                .map_err(store_error)
+        })
+        .await
+    }
+}
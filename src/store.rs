@@ -0,0 +1,223 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use crate::time::{self, TimeDelta};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, hash::{BuildHasher, Hasher}, sync::{Arc, Mutex}, time::Instant};
+
+use crate::{logging::log_at, AccessToken, GitHubAuthenticatorError, LoggingPolicy, TokenRequest};
+
+/// Storage for installation access tokens that is shared between authenticators, and between the
+/// replicas of a service when backed by shared storage such as Redis or a SQL database.
+///
+/// Leases let replicas coordinate refreshes: the replica that acquires the lease for a key mints
+/// the new token and puts it in the store, while the others wait for it to appear. Stores that do
+/// not support leases can rely on the default implementations, in which case every replica mints
+/// its own tokens.
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// Get the token stored under `key`, if any.
+    async fn get(&self, key: &str) -> Result<Option<AccessToken>, GitHubAuthenticatorError>;
+
+    /// Store `token` under `key`, replacing any token stored previously.
+    async fn put(&self, key: &str, token: &AccessToken) -> Result<(), GitHubAuthenticatorError>;
+
+    /// Remove the token stored under `key`, if any.
+    async fn remove(&self, key: &str) -> Result<(), GitHubAuthenticatorError>;
+
+    /// Try to acquire the refresh lease for `key` on behalf of `holder`, returning whether the
+    /// lease was acquired. A lease that is not released expires after `ttl`, so that a replica that
+    /// disappears while refreshing does not block the others indefinitely. Acquiring a lease that
+    /// `holder` already holds extends it.
+    async fn try_acquire_lease(&self, key: &str, holder: &str, ttl: std::time::Duration) -> Result<bool, GitHubAuthenticatorError> {
+        let _ = (key, holder, ttl);
+        Ok(true)
+    }
+
+    /// Release the refresh lease for `key` if it is held by `holder`.
+    async fn release_lease(&self, key: &str, holder: &str) -> Result<(), GitHubAuthenticatorError> {
+        let _ = (key, holder);
+        Ok(())
+    }
+}
+
+/// A [`TokenStore`] that keeps tokens in memory, for sharing tokens between authenticators within
+/// a single process. Cloning is cheap, and all clones share the same tokens and leases.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryTokenStore {
+    tokens: Arc<Mutex<HashMap<String, AccessToken>>>,
+    leases: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl InMemoryTokenStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TokenStore for InMemoryTokenStore {
+    async fn get(&self, key: &str) -> Result<Option<AccessToken>, GitHubAuthenticatorError> {
+        Ok(self.tokens.lock().unwrap().get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, token: &AccessToken) -> Result<(), GitHubAuthenticatorError> {
+        self.tokens.lock().unwrap().insert(key.to_string(), token.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), GitHubAuthenticatorError> {
+        self.tokens.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn try_acquire_lease(&self, key: &str, holder: &str, ttl: std::time::Duration) -> Result<bool, GitHubAuthenticatorError> {
+        let mut leases = self.leases.lock().unwrap();
+        let now = Instant::now();
+
+        match leases.get(key) {
+            Some((current, expires_at)) if current != holder && *expires_at > now => Ok(false),
+            _ => {
+                leases.insert(key.to_string(), (holder.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release_lease(&self, key: &str, holder: &str) -> Result<(), GitHubAuthenticatorError> {
+        let mut leases = self.leases.lock().unwrap();
+
+        if leases.get(key).map(|(current, _)| current == holder).unwrap_or(false) {
+            leases.remove(key);
+        }
+
+        Ok(())
+    }
+}
+
+// How long a replica may hold the refresh lease before others assume it has failed
+const LEASE_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+
+// How often replicas that are waiting on another replica's refresh check the store
+const LEASE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+// A token store bound to the key of a single installation and token request
+#[derive(Clone)]
+pub(crate) struct SharedStore {
+    store: Arc<dyn TokenStore>,
+    key: String,
+    holder: String,
+}
+
+impl std::fmt::Debug for SharedStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedStore").field("key", &self.key).finish()
+    }
+}
+
+impl SharedStore {
+    // Tokens are keyed by app, installation and a digest of the request, so that replicas with the
+    // same configuration share tokens regardless of the order in which they were created
+    pub(crate) fn new(store: Arc<dyn TokenStore>, app_id: u32, installation_id: u32, request: &TokenRequest) -> Self {
+        let request = serde_json::to_vec(request).unwrap_or_default();
+        let key = format!("github-app-authenticator/{}/{}/{}", app_id, installation_id, hex::encode(Sha256::digest(&request)));
+
+        // Identifies this authenticator among the replicas contending for a lease
+        let holder = format!("{:016x}", std::collections::hash_map::RandomState::new().build_hasher().finish());
+
+        Self { store, key, holder }
+    }
+
+    // Get a token that remains valid for `min_duration` from the store, or mint one with `mint`
+    // while holding the lease. A `min_duration` of `None` always mints a new token. Failures of
+    // the store are logged and fall back to minting, so that an unavailable store does not prevent
+    // tokens from being issued
+    pub(crate) async fn fetch<F, Fut>(&self, min_duration: Option<TimeDelta>, logging: &LoggingPolicy, mint: F) -> Result<AccessToken, GitHubAuthenticatorError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<AccessToken, GitHubAuthenticatorError>>,
+    {
+        let deadline = Instant::now() + LEASE_TTL;
+
+        loop {
+            if let Some(min_duration) = min_duration {
+                match self.valid_token(min_duration).await {
+                    Ok(Some(token)) => return Ok(token),
+                    Ok(None) => {}
+                    Err(err) => {
+                        log_at!(logging.internal_errors(), ?err, "Failed to read installation access token from token store");
+                        return mint().await;
+                    }
+                }
+            }
+
+            match self.store.try_acquire_lease(&self.key, &self.holder, LEASE_TTL).await {
+                Ok(true) => {
+                    // Another replica may have stored a token between the check above and
+                    // acquiring the lease
+                    let stored = match min_duration {
+                        Some(min_duration) => self.valid_token(min_duration).await.ok().flatten(),
+                        None => None,
+                    };
+
+                    let result = match stored {
+                        Some(token) => Ok(token),
+                        None => {
+                            let result = mint().await;
+
+                            if let Ok(token) = &result {
+                                if let Err(err) = self.store.put(&self.key, token).await {
+                                    log_at!(logging.internal_errors(), ?err, "Failed to write installation access token to token store");
+                                }
+                            }
+
+                            result
+                        }
+                    };
+
+                    if let Err(err) = self.store.release_lease(&self.key, &self.holder).await {
+                        log_at!(logging.internal_errors(), ?err, "Failed to release token store lease");
+                    }
+
+                    return result;
+                }
+                Ok(false) if Instant::now() < deadline => {
+                    tokio::time::sleep(LEASE_POLL_INTERVAL).await;
+                }
+                Ok(false) => {
+                    log_at!(logging.internal_errors(), "Timed out waiting on token store lease held by another replica");
+                    return mint().await;
+                }
+                Err(err) => {
+                    log_at!(logging.internal_errors(), ?err, "Failed to acquire token store lease");
+                    return mint().await;
+                }
+            }
+        }
+    }
+
+    // Remove the token from the store, returning it
+    pub(crate) async fn take(&self) -> Result<Option<AccessToken>, GitHubAuthenticatorError> {
+        let token = self.store.get(&self.key).await?;
+
+        if token.is_some() {
+            self.store.remove(&self.key).await?;
+        }
+
+        Ok(token)
+    }
+
+    // Tokens are considered expired 5 minutes early, as they are by the refreshing authenticator
+    async fn valid_token(&self, min_duration: TimeDelta) -> Result<Option<AccessToken>, GitHubAuthenticatorError> {
+        Ok(self
+            .store
+            .get(&self.key)
+            .await?
+            .filter(|token| token.expires_at - time::minutes(5) > time::now() + min_duration))
+    }
+}
@@ -0,0 +1,315 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, Timestamp, TimeDelta};
+use http::HeaderValue;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rsa::{pkcs1::{EncodeRsaPrivateKey, EncodeRsaPublicKey, LineEnding}, RsaPrivateKey};
+use serde::Deserialize;
+use std::sync::OnceLock;
+use wiremock::{matchers::{method, path}, Match, Mock, MockServer, Request, ResponseTemplate, Times};
+
+use crate::{permissions::Permissions, GitHubAppAuthenticator, TokenRequest};
+
+/// A mock of the GitHub API for testing code that fetches installation access tokens, backed by a
+/// [`wiremock::MockServer`]. Mocked endpoints only respond to requests that are authenticated by a
+/// JWT signed with the key of the mock app.
+///
+/// ```no_run
+/// # use github_app_authenticator::{test_util::MockGitHubApp, TokenRequest};
+/// # async fn example() {
+/// let github = MockGitHubApp::start().await;
+/// github.installation(1).with_token("test-token").mount().await;
+///
+/// let token = github
+///     .authenticator()
+///     .installation_authenticator(1)
+///     .access_token(&TokenRequest::default())
+///     .await
+///     .unwrap();
+/// assert_eq!("test-token", token);
+/// # }
+/// ```
+pub struct MockGitHubApp {
+    server: MockServer,
+    app_id: u32,
+}
+
+impl MockGitHubApp {
+    /// Start a mock server for an app with id 1.
+    pub async fn start() -> Self {
+        Self::start_with_app_id(1).await
+    }
+
+    /// Start a mock server for an app with the given id.
+    pub async fn start_with_app_id(app_id: u32) -> Self {
+        Self {
+            server: MockServer::start().await,
+            app_id,
+        }
+    }
+
+    /// The underlying mock server, for mounting mocks of other endpoints.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// The base uri of the mock server.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The id of the mock app.
+    pub fn app_id(&self) -> u32 {
+        self.app_id
+    }
+
+    /// The private key of the mock app in PEM format. Generating a key is slow, particularly in
+    /// debug builds, so all mock apps within a process share a single key.
+    pub fn private_key(&self) -> Vec<u8> {
+        private_key()
+    }
+
+    /// Create an authenticator for the mock app that sends its requests to the mock server.
+    pub fn authenticator(&self) -> GitHubAppAuthenticator {
+        let mut app = GitHubAppAuthenticator::new(
+            self.app_id,
+            self.private_key(),
+            HeaderValue::from_static("github-app-authenticator-test"),
+        );
+        app.with_base_uri(self.server.uri());
+
+        app
+    }
+
+    /// Start configuring the response to token requests for an installation.
+    pub fn installation(&self, installation_id: u32) -> MockInstallation<'_> {
+        MockInstallation {
+            app: self,
+            installation_id,
+            token: format!("ghs_mock_token_{}", installation_id),
+            expires_in: time::hours(1),
+            expires_at: None,
+            permissions: None,
+            status: 201,
+            delay: None,
+            limit: None,
+            expected: None,
+        }
+    }
+
+    /// Accept the revocation of any installation access token.
+    pub async fn mount_revocation(&self) {
+        Mock::given(method("DELETE"))
+            .and(path("/installation/token"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// The token requests that the mock server received for an installation, in the order in which
+    /// they were received, for asserting on the permissions and repositories that were requested.
+    pub async fn received_token_requests(&self, installation_id: u32) -> Vec<TokenRequest> {
+        let token_path = format!("/app/installations/{}/access_tokens", installation_id);
+
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.method == wiremock::http::Method::Post && request.url.path() == token_path)
+            .map(|request| serde_json::from_slice(&request.body).unwrap_or_default())
+            .collect()
+    }
+}
+
+/// The response of a [`MockGitHubApp`] to token requests for a single installation. By default, a
+/// token named after the installation is issued that is valid for an hour.
+pub struct MockInstallation<'a> {
+    app: &'a MockGitHubApp,
+    installation_id: u32,
+    token: String,
+    expires_in: TimeDelta,
+    expires_at: Option<Timestamp>,
+    permissions: Option<Permissions>,
+    status: u16,
+    delay: Option<std::time::Duration>,
+    limit: Option<u64>,
+    expected: Option<Times>,
+}
+
+impl MockInstallation<'_> {
+    /// Issue the given token.
+    pub fn with_token<T>(mut self, token: T) -> Self where T: ToString {
+        self.token = token.to_string();
+        self
+    }
+
+    /// Issue tokens that expire `expires_in` after they were requested.
+    pub fn with_expires_in(mut self, expires_in: TimeDelta) -> Self {
+        self.expires_in = expires_in;
+        self
+    }
+
+    /// Issue tokens that expire at the given time, regardless of when they were requested.
+    pub fn with_expires_at(mut self, expires_at: Timestamp) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Report the given permissions as granted to issued tokens. By default, the permissions that
+    /// were requested are reported.
+    pub fn with_permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Respond with the given status instead of issuing a token, for instance 404 for an
+    /// installation that does not exist.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Delay responses by the given duration, for instance to exercise concurrent requests.
+    pub fn with_delay(mut self, delay: std::time::Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Only respond to the first `count` token requests, so that later responses can be mounted
+    /// separately.
+    pub fn up_to_n_times(mut self, count: u64) -> Self {
+        self.limit = Some(count);
+        self
+    }
+
+    /// Verify that the given number of tokens are requested before the mock server is dropped,
+    /// either an exact count or a range.
+    pub fn expect<T>(mut self, times: T) -> Self where T: Into<Times> {
+        self.expected = Some(times.into());
+        self
+    }
+
+    /// Mount the configured response on the mock server.
+    pub async fn mount(self) {
+        let mut mock = Mock::given(method("POST"))
+            .and(path(format!("/app/installations/{}/access_tokens", self.installation_id)))
+            .and(ValidAppJwt { app_id: self.app.app_id })
+            .respond_with(TokenResponder {
+                token: self.token,
+                expires_in: self.expires_in,
+                expires_at: self.expires_at,
+                permissions: self.permissions,
+                status: self.status,
+                delay: self.delay,
+            });
+
+        if let Some(limit) = self.limit {
+            mock = mock.up_to_n_times(limit);
+        }
+
+        if let Some(expected) = self.expected {
+            mock = mock.expect(expected);
+        }
+
+        mock.mount(&self.app.server).await;
+    }
+}
+
+struct TokenResponder {
+    token: String,
+    expires_in: TimeDelta,
+    expires_at: Option<Timestamp>,
+    permissions: Option<Permissions>,
+    status: u16,
+    delay: Option<std::time::Duration>,
+}
+
+impl wiremock::Respond for TokenResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let response = if self.status != 201 {
+            ResponseTemplate::new(self.status).set_body_json(serde_json::json!({
+                "message": "Mocked failure",
+            }))
+        } else {
+            let request = serde_json::from_slice::<TokenRequest>(&request.body).unwrap_or_default();
+            let permissions = self.permissions.clone().or(request.permissions);
+
+            ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "token": self.token,
+                "expires_at": self.expires_at.unwrap_or_else(|| time::now() + self.expires_in),
+                "permissions": permissions,
+                "repository_selection": if request.repositories.is_some() || request.repository_ids.is_some() { "selected" } else { "all" },
+            }))
+        };
+
+        match self.delay {
+            Some(delay) => response.set_delay(delay),
+            None => response,
+        }
+    }
+}
+
+// Matches requests that are authenticated by an unexpired JWT of the app
+struct ValidAppJwt {
+    app_id: u32,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: u32,
+}
+
+impl Match for ValidAppJwt {
+    fn matches(&self, request: &Request) -> bool {
+        let jwt = request
+            .headers
+            .iter()
+            .find(|(name, _)| name.as_str().eq_ignore_ascii_case("authorization"))
+            .and_then(|(_, values)| values.last().as_str().strip_prefix("Bearer ").map(str::to_string));
+
+        let key = match DecodingKey::from_rsa_pem(&test_key().public) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+        jwt.and_then(|jwt| jsonwebtoken::decode::<Claims>(&jwt, &key, &Validation::new(Algorithm::RS256)).ok())
+            .map(|data| data.claims.iss == self.app_id)
+            .unwrap_or(false)
+    }
+}
+
+// The private key shared by all mock apps in PEM format
+pub(crate) fn private_key() -> Vec<u8> {
+    test_key().private.clone()
+}
+
+struct TestKey {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+fn test_key() -> &'static TestKey {
+    static KEY: OnceLock<TestKey> = OnceLock::new();
+
+    KEY.get_or_init(|| {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("generated test key");
+
+        TestKey {
+            private: key
+                .to_pkcs1_pem(LineEnding::default())
+                .expect("encoded test key")
+                .as_bytes()
+                .to_vec(),
+            public: key
+                .to_public_key()
+                .to_pkcs1_pem(LineEnding::default())
+                .expect("encoded test key")
+                .into_bytes(),
+        }
+    })
+}
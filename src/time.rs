@@ -0,0 +1,211 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+// The time backend that the API of this crate is expressed in. chrono is used when the `chrono`
+// feature is enabled, and jiff otherwise. The rest of the crate only goes through the functions
+// below for anything beyond comparing, adding and subtracting, so that both backends behave the
+// same
+
+#[cfg(not(any(feature = "chrono", feature = "jiff")))]
+compile_error!("either the `chrono` or the `jiff` feature must be enabled to select a time backend");
+
+#[cfg(feature = "chrono")]
+mod backend {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+
+    /// A point in time, as used throughout the API of this crate. This is
+    /// `chrono::DateTime<Utc>` when the `chrono` feature is enabled, and `jiff::Timestamp`
+    /// otherwise.
+    pub type Timestamp = DateTime<Utc>;
+
+    /// A signed span of time, as used throughout the API of this crate. This is
+    /// `chrono::TimeDelta` when the `chrono` feature is enabled, and `jiff::SignedDuration`
+    /// otherwise.
+    pub type TimeDelta = chrono::TimeDelta;
+
+    pub(crate) fn now() -> Timestamp {
+        Utc::now()
+    }
+
+    pub(crate) const fn seconds(seconds: i64) -> TimeDelta {
+        TimeDelta::seconds(seconds)
+    }
+
+    pub(crate) const fn minutes(minutes: i64) -> TimeDelta {
+        TimeDelta::minutes(minutes)
+    }
+
+    pub(crate) const fn hours(hours: i64) -> TimeDelta {
+        TimeDelta::hours(hours)
+    }
+
+    pub(crate) const fn zero() -> TimeDelta {
+        TimeDelta::zero()
+    }
+
+    // The time from `earlier` until `later`, which is negative if `later` is before `earlier`
+    pub(crate) fn between(later: Timestamp, earlier: Timestamp) -> TimeDelta {
+        later - earlier
+    }
+
+    pub(crate) fn whole_seconds(delta: TimeDelta) -> i64 {
+        delta.num_seconds()
+    }
+
+    // Negative deltas are converted to zero
+    pub(crate) fn to_std(delta: TimeDelta) -> std::time::Duration {
+        delta.to_std().unwrap_or_default()
+    }
+
+    // Durations beyond the range of the backend are clamped to its bounds
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_std(duration: std::time::Duration) -> TimeDelta {
+        TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX)
+    }
+
+    pub(crate) fn from_unix_seconds(seconds: i64) -> Option<Timestamp> {
+        DateTime::from_timestamp(seconds, 0)
+    }
+
+    pub(crate) fn unix_seconds(timestamp: Timestamp) -> i64 {
+        timestamp.timestamp()
+    }
+
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn unix_millis(timestamp: Timestamp) -> i64 {
+        timestamp.timestamp_millis()
+    }
+
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn format_rfc3339(timestamp: Timestamp) -> String {
+        timestamp.to_rfc3339()
+    }
+
+    #[cfg(all(test, feature = "reqwest"))]
+    pub(crate) fn format_rfc2822(timestamp: Timestamp) -> String {
+        timestamp.to_rfc2822()
+    }
+
+    pub(crate) fn parse_rfc2822(value: &str) -> Option<Timestamp> {
+        DateTime::parse_from_rfc2822(value).ok().map(|value| value.with_timezone(&Utc))
+    }
+
+    // Parse RFC 3339 and the variants of it that are accepted in `token::parse_expires_at`
+    pub(crate) fn parse_rfc3339(value: &str) -> Option<Timestamp> {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+
+        let value = value.replacen(' ', "T", 1);
+
+        if let Ok(timestamp) = DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f%z") {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+
+        NaiveDateTime::parse_from_str(value.trim_end_matches(['Z', 'z']), "%Y-%m-%dT%H:%M:%S%.f")
+            .ok()
+            .map(|timestamp| timestamp.and_utc())
+    }
+}
+
+#[cfg(not(feature = "chrono"))]
+mod backend {
+    use jiff::{civil::DateTime, tz::TimeZone};
+
+    /// A point in time, as used throughout the API of this crate. This is
+    /// `chrono::DateTime<Utc>` when the `chrono` feature is enabled, and `jiff::Timestamp`
+    /// otherwise.
+    pub type Timestamp = jiff::Timestamp;
+
+    /// A signed span of time, as used throughout the API of this crate. This is
+    /// `chrono::TimeDelta` when the `chrono` feature is enabled, and `jiff::SignedDuration`
+    /// otherwise.
+    pub type TimeDelta = jiff::SignedDuration;
+
+    pub(crate) fn now() -> Timestamp {
+        Timestamp::now()
+    }
+
+    pub(crate) const fn seconds(seconds: i64) -> TimeDelta {
+        TimeDelta::from_secs(seconds)
+    }
+
+    pub(crate) const fn minutes(minutes: i64) -> TimeDelta {
+        TimeDelta::from_mins(minutes)
+    }
+
+    pub(crate) const fn hours(hours: i64) -> TimeDelta {
+        TimeDelta::from_hours(hours)
+    }
+
+    pub(crate) const fn zero() -> TimeDelta {
+        TimeDelta::ZERO
+    }
+
+    // The time from `earlier` until `later`, which is negative if `later` is before `earlier`
+    pub(crate) fn between(later: Timestamp, earlier: Timestamp) -> TimeDelta {
+        later.duration_since(earlier)
+    }
+
+    pub(crate) fn whole_seconds(delta: TimeDelta) -> i64 {
+        delta.as_secs()
+    }
+
+    // Negative deltas are converted to zero
+    pub(crate) fn to_std(delta: TimeDelta) -> std::time::Duration {
+        std::time::Duration::try_from(delta).unwrap_or_default()
+    }
+
+    // Durations beyond the range of the backend are clamped to its bounds
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_std(duration: std::time::Duration) -> TimeDelta {
+        TimeDelta::try_from(duration).unwrap_or(TimeDelta::MAX)
+    }
+
+    pub(crate) fn from_unix_seconds(seconds: i64) -> Option<Timestamp> {
+        Timestamp::from_second(seconds).ok()
+    }
+
+    pub(crate) fn unix_seconds(timestamp: Timestamp) -> i64 {
+        timestamp.as_second()
+    }
+
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn unix_millis(timestamp: Timestamp) -> i64 {
+        timestamp.as_millisecond()
+    }
+
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn format_rfc3339(timestamp: Timestamp) -> String {
+        timestamp.to_string()
+    }
+
+    #[cfg(all(test, feature = "reqwest"))]
+    pub(crate) fn format_rfc2822(timestamp: Timestamp) -> String {
+        jiff::fmt::rfc2822::DateTimePrinter::new().timestamp_to_rfc9110_string(&timestamp).unwrap()
+    }
+
+    pub(crate) fn parse_rfc2822(value: &str) -> Option<Timestamp> {
+        jiff::fmt::rfc2822::parse(value).ok().map(|value| value.timestamp())
+    }
+
+    // Parse RFC 3339 and the variants of it that are accepted in `token::parse_expires_at`. Times
+    // without an offset are taken to be UTC
+    pub(crate) fn parse_rfc3339(value: &str) -> Option<Timestamp> {
+        if let Ok(timestamp) = value.parse::<Timestamp>() {
+            return Some(timestamp);
+        }
+
+        value
+            .parse::<DateTime>()
+            .ok()
+            .and_then(|value| value.to_zoned(TimeZone::UTC).ok())
+            .map(|value| value.timestamp())
+    }
+}
+
+pub use backend::{TimeDelta, Timestamp};
+pub(crate) use backend::*;
@@ -4,28 +4,240 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use chrono::{DateTime, Utc, Duration};
-use serde::Serialize;
+use crate::time::{self, Timestamp, TimeDelta};
+use http::HeaderValue;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{fmt::Debug, ops::Sub};
 
-use crate::{permissions::Permissions, GitHubInstallationTokenResponse};
+use crate::{permissions::Permissions, GitHubAuthenticatorError, GitHubHost, RateLimit};
+
+// Tokens are considered expired this long before the expiration time that GitHub specifies to
+// alleviate potential clock skew and race conditions
+pub(crate) static EXPIRY_MARGIN: TimeDelta = time::minutes(5);
+
+// The longest minimum validity that background refreshes accept. GitHub issues tokens that are
+// valid for an hour, so this leaves at least 10 minutes between refreshes
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) static MAX_MIN_VALIDITY: TimeDelta = time::minutes(45);
 
 /// A request for generating an access token with a specific set of permissions for a specific set
 /// of repositories. The GitHub App must already be granted all of the requested permissions on the
 /// requested repositories.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct TokenRequest {
+    /// The permissions to grant the token. Defaults to all of the permissions granted to the
+    /// installation.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub permissions: Option<Permissions>,
+    /// The names of the repositories to scope the token to, without the owner prefix.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub repositories: Option<Vec<String>>,
+    /// The ids of the repositories to scope the token to.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub repository_ids: Option<Vec<u32>>,
+    pub repository_ids: Option<Vec<u64>>,
+}
+
+/// An installation access token along with the scope that GitHub granted it.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AccessToken {
+    /// The access token to send as a bearer token.
+    pub token: String,
+    /// The time at which GitHub will stop accepting the token.
+    #[serde(deserialize_with = "deserialize_expires_at")]
+    pub expires_at: Timestamp,
+    /// The permissions granted to the token. Individual permissions are omitted if GitHub reports
+    /// them at a level that this crate does not understand.
+    #[serde(default, deserialize_with = "deserialize_granted_permissions")]
+    pub permissions: Option<Permissions>,
+    /// Whether the token has access to all repositories of the installation or only a selection.
+    #[serde(default)]
+    pub repository_selection: Option<RepositorySelection>,
+    /// The repositories the token is scoped to. Only present when the token was requested for a
+    /// specific set of repositories.
+    #[serde(default)]
+    pub repositories: Option<Vec<Repository>>,
+    /// The rate limit of requests authenticated as the app, as reported by the response that
+    /// issued the token.
+    #[serde(skip)]
+    pub rate_limit: Option<RateLimit>,
+    /// The fields of the response that this crate does not model yet, such as fields that GitHub
+    /// has introduced since this crate was released.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AccessToken {
+    /// Create an Authorization header value that authenticates requests with this token. The
+    /// value is marked as sensitive so that it is omitted from debug output.
+    pub fn authorization_header(&self) -> Result<HeaderValue, GitHubAuthenticatorError> {
+        bearer_authorization(&self.token)
+    }
+
+    /// Create an https url for cloning the repository `owner/repo` from `host` that authenticates
+    /// with this token.
+    pub fn clone_url(&self, host: &GitHubHost, owner: &str, repo: &str) -> String {
+        authenticated_clone_url(host, &self.token, owner, repo)
+    }
+
+    /// Create a clone url in the form of [`Self::clone_url`] with the token redacted, for
+    /// including in logs and error messages.
+    pub fn redacted_clone_url(&self, host: &GitHubHost, owner: &str, repo: &str) -> String {
+        authenticated_clone_url(host, "***", owner, repo)
+    }
+
+    /// Check that the token has been granted at least the given permissions. Tokens without
+    /// reported permissions do not grant anything.
+    pub fn grants(&self, permissions: &Permissions) -> bool {
+        self.permissions
+            .as_ref()
+            .map(|granted| permissions.is_subset_of(granted))
+            .unwrap_or(false)
+    }
+}
+
+// GitHub accepts installation tokens as the password of the x-access-token user
+fn authenticated_clone_url(host: &GitHubHost, token: &str, owner: &str, repo: &str) -> String {
+    let endpoint = host.web_endpoint();
+    let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", &endpoint));
+
+    format!("{}://x-access-token:{}@{}/{}/{}.git", scheme, token, host, owner, repo)
+}
+
+// Create a bearer Authorization header value that is omitted from debug output
+pub(crate) fn bearer_authorization(token: &str) -> Result<HeaderValue, GitHubAuthenticatorError> {
+    let mut authorization = HeaderValue::from_str(&format!("Bearer {}", token))
+        .map_err(GitHubAuthenticatorError::FailedToCreateHeader)?;
+    authorization.set_sensitive(true);
+
+    Ok(authorization)
+}
+
+// A token that GitHub has issued is usable regardless of whether its permissions can be parsed, so
+// failing to parse them (for instance due to a newly introduced permission level) must not fail
+// the token request. Only the permissions that fail to parse are dropped. See
+// `undecodable_permissions` for reporting the failure
+pub(crate) fn deserialize_granted_permissions<'de, D>(deserializer: D) -> Result<Option<Permissions>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+
+    Ok(value.and_then(|value| decode_permissions(value).0))
+}
+
+// Decode each permission separately, so that a single permission that can not be decoded does not
+// drop the others. Returns the permissions that could be decoded along with the errors of those that
+// could not
+fn decode_permissions(value: serde_json::Value) -> (Option<Permissions>, Vec<serde_json::Error>) {
+    let entries = match value {
+        serde_json::Value::Object(entries) => entries,
+        value => return match serde_json::from_value(value) {
+            Ok(permissions) => (Some(permissions), vec![]),
+            Err(err) => (None, vec![err]),
+        },
+    };
+
+    let mut decodable = serde_json::Map::new();
+    let mut errors = vec![];
+
+    for (name, level) in entries {
+        let entry = serde_json::Map::from_iter([(name, level)]);
+
+        match serde_json::from_value::<Permissions>(serde_json::Value::Object(entry.clone())) {
+            Ok(_) => decodable.extend(entry),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    (serde_json::from_value(serde_json::Value::Object(decodable)).ok(), errors)
+}
+
+// The reasons that permissions of an access token response were dropped while decoding it, if any
+// were, so that callers can log them according to their logging policy
+pub(crate) fn undecodable_permissions(body: &[u8]) -> Vec<serde_json::Error> {
+    let permissions = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body.get("permissions").cloned())
+        .unwrap_or_default();
+
+    if permissions.is_null() {
+        return vec![];
+    }
+
+    decode_permissions(permissions).1
+}
+
+// Parse the expiry of a token in any of the RFC 3339 variants that GitHub, GitHub Enterprise Server
+// and proxies in between have been observed to send: with or without fractional seconds, with `Z`
+// or a numeric offset, an offset without a colon, a space instead of `T`, or no offset at all, in
+// which case the time is taken to be UTC
+pub(crate) fn parse_expires_at(value: &str) -> Option<Timestamp> {
+    time::parse_rfc3339(value.trim())
+}
+
+fn deserialize_expires_at<'de, D>(deserializer: D) -> Result<Timestamp, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+
+    parse_expires_at(&value)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid access token expiry {:?}", value)))
+}
+
+// Decode an access token response, failing with the raw expiry if it is the expiry that could not
+// be parsed
+pub(crate) fn decode_access_token(body: &[u8]) -> Result<AccessToken, GitHubAuthenticatorError> {
+    serde_json::from_slice(body).map_err(|err| {
+        let expires_at = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|body| body.get("expires_at").cloned());
+
+        match expires_at {
+            Some(serde_json::Value::String(value)) if parse_expires_at(&value).is_some() => {
+                GitHubAuthenticatorError::access_token_decode(err, body)
+            }
+            Some(serde_json::Value::String(value)) => GitHubAuthenticatorError::FailedToParseTokenExpiry(value),
+            Some(value) => GitHubAuthenticatorError::FailedToParseTokenExpiry(value.to_string()),
+            None => GitHubAuthenticatorError::access_token_decode(err, body),
+        }
+    })
+}
+
+impl Debug for AccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("expires_at", &self.expires_at)
+            .field("permissions", &self.permissions)
+            .field("repository_selection", &self.repository_selection)
+            .field("repositories", &self.repositories)
+            .field("rate_limit", &self.rate_limit)
+            // Unknown fields may hold credentials, so only their names are included
+            .field("extra", &self.extra.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// The set of repositories an access token has access to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositorySelection {
+    All,
+    Selected,
+}
+
+/// A repository that an access token is scoped to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Repository {
+    pub id: u64,
+    pub name: String,
+    pub full_name: String,
 }
 
 pub(crate) struct GitHubInstallationToken {
-    pub access_token: String,
-    pub expires_at: DateTime<Utc>,
+    pub access_token: AccessToken,
+    pub expires_at: Timestamp,
 }
 
 impl Debug for GitHubInstallationToken {
@@ -36,13 +248,11 @@ impl Debug for GitHubInstallationToken {
     }
 }
 
-impl From<GitHubInstallationTokenResponse> for GitHubInstallationToken {
-    fn from(value: GitHubInstallationTokenResponse) -> Self {
+impl From<AccessToken> for GitHubInstallationToken {
+    fn from(value: AccessToken) -> Self {
         Self {
-            access_token: value.token,
-            // Subtract 5 minutes from the expiration time that GitHub specifies to alleviate
-            // potential clock skew and race conditions
-            expires_at: value.expires_at.sub(Duration::minutes(5)),
+            expires_at: value.expires_at.sub(EXPIRY_MARGIN),
+            access_token: value,
         }
     }
-}
\ No newline at end of file
+}
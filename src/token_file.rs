@@ -0,0 +1,201 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::time::{self, TimeDelta};
+use std::{io::Write, path::{Path, PathBuf}};
+
+use crate::logging::log_at;
+use crate::token::MAX_MIN_VALIDITY;
+use crate::{AccessToken, GitHubAuthenticatorError, RefreshingGitHubInstallationAuthenticator, Shutdown};
+
+/// Keeps files on disk up to date with a fresh installation access token, for processes that can
+/// not fetch tokens themselves, such as non-Rust processes sharing a pod with a sidecar. Files are
+/// replaced atomically, so readers never observe a partially written token.
+///
+/// Besides the token itself, a `.git-credentials` file for git's `store` credential helper and a
+/// `.netrc` file may be written.
+#[derive(Clone, Debug)]
+pub struct TokenFileWriter {
+    authenticator: RefreshingGitHubInstallationAuthenticator,
+    token_path: PathBuf,
+    git_credentials_path: Option<PathBuf>,
+    netrc_path: Option<PathBuf>,
+    mode: u32,
+    min_validity: TimeDelta,
+    retry_interval: std::time::Duration,
+}
+
+impl TokenFileWriter {
+    /// Create a writer that writes tokens from the given authenticator to `token_path`.
+    pub fn new<P>(authenticator: RefreshingGitHubInstallationAuthenticator, token_path: P) -> Self where P: Into<PathBuf> {
+        Self {
+            authenticator,
+            token_path: token_path.into(),
+            git_credentials_path: None,
+            netrc_path: None,
+            mode: 0o600,
+            min_validity: time::minutes(15),
+            retry_interval: std::time::Duration::from_secs(30),
+        }
+    }
+
+    /// Additionally write a `.git-credentials` file to `path`.
+    pub fn with_git_credentials<P>(mut self, path: P) -> Self where P: Into<PathBuf> {
+        self.git_credentials_path = Some(path.into());
+        self
+    }
+
+    /// Additionally write a `.netrc` file to `path`.
+    pub fn with_netrc<P>(mut self, path: P) -> Self where P: Into<PathBuf> {
+        self.netrc_path = Some(path.into());
+        self
+    }
+
+    /// Configure the mode bits of the written files. Defaults to `0o600`. Ignored on platforms
+    /// other than unix.
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Configure how long the token on disk remains valid at the least. The token is replaced
+    /// before its remaining lifetime falls below this duration. Defaults to 15 minutes.
+    ///
+    /// GitHub issues tokens that are valid for an hour, so durations are capped at 45 minutes to
+    /// avoid replacing the token on every wake up.
+    pub fn with_min_validity(mut self, min_validity: TimeDelta) -> Self {
+        self.min_validity = min_validity.clamp(time::zero(), MAX_MIN_VALIDITY);
+        self
+    }
+
+    /// Configure how long to wait before trying again after failing to fetch or write a token.
+    /// Defaults to 30 seconds.
+    pub fn with_retry_interval(mut self, interval: std::time::Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Fetch a token that remains valid for the configured minimum validity and write it to all
+    /// configured files.
+    pub async fn write(&self) -> Result<AccessToken, GitHubAuthenticatorError> {
+        let token = self.authenticator.access_token_detailed_valid_for(self.min_validity).await?;
+
+        write_atomically(&self.token_path, self.mode, token.token.as_bytes())?;
+
+        if let Some(path) = &self.git_credentials_path {
+            write_atomically(path, self.mode, self.git_credentials(&token.token).as_bytes())?;
+        }
+
+        if let Some(path) = &self.netrc_path {
+            write_atomically(path, self.mode, self.netrc(&token.token).as_bytes())?;
+        }
+
+        Ok(token)
+    }
+
+    /// Keep the files up to date until the returned future is dropped. Failures are logged and
+    /// retried after the configured retry interval, while the previously written token remains
+    /// in place.
+    pub async fn run(&self) {
+        self.run_until(&Shutdown::new()).await
+    }
+
+    /// Keep the files up to date until shutdown is signalled, see [`Self::run`]. A token that is
+    /// being written when shutdown is signalled is written in full. The token is revoked before
+    /// returning if the shutdown is configured to revoke tokens.
+    pub async fn run_until(&self, shutdown: &Shutdown) {
+        while !shutdown.is_signalled() {
+            let delay = match self.write().await {
+                Ok(token) => {
+                    let logging = self.authenticator.logging();
+                    log_at!(logging.token_requests(), installation_id = %logging.id(self.authenticator.installation_id()), path = ?self.token_path, expires_at = ?token.expires_at, "Wrote installation access token");
+
+                    // Wake up once the token is about to fall below the minimum validity. The
+                    // refreshing authenticator already considers tokens expired a few minutes
+                    // early, so this always yields a new token
+                    time::to_std(time::between(token.expires_at - self.min_validity, time::now()))
+                        .max(std::time::Duration::from_secs(1))
+                }
+                Err(err) => {
+                    let logging = self.authenticator.logging();
+                    log_at!(logging.request_failures(), ?err, installation_id = %logging.id(self.authenticator.installation_id()), path = ?self.token_path, "Failed to write installation access token");
+                    self.retry_interval
+                }
+            };
+
+            shutdown.unless_signalled(tokio::time::sleep(delay)).await;
+        }
+
+        if shutdown.revokes_tokens() {
+            if let Err(err) = self.authenticator.revoke().await {
+                let logging = self.authenticator.logging();
+                log_at!(logging.request_failures(), ?err, installation_id = %logging.id(self.authenticator.installation_id()), path = ?self.token_path, "Failed to revoke installation access token on shutdown");
+            }
+        }
+    }
+
+    // Lines of the form `https://x-access-token:<token>@github.com`, as read by `git credential-store`
+    fn git_credentials(&self, token: &str) -> String {
+        let endpoint = self.authenticator.host().web_endpoint();
+        let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", &endpoint));
+
+        format!("{}://x-access-token:{}@{}\n", scheme, token, host)
+    }
+
+    // Entries for the hosts of both the web interface and the API
+    fn netrc(&self, token: &str) -> String {
+        let host = self.authenticator.host();
+        let mut machines = vec![host_of(&host.web_endpoint()), host_of(&host.api_endpoint())];
+        machines.dedup();
+
+        machines
+            .into_iter()
+            .map(|machine| format!("machine {}\nlogin x-access-token\npassword {}\n", machine, token))
+            .collect()
+    }
+}
+
+fn host_of(endpoint: &str) -> String {
+    let (_, rest) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+    rest.split('/').next().unwrap_or(rest).to_string()
+}
+
+// Write to a temporary file next to the destination and move it into place, so that readers see
+// either the previous or the new contents
+fn write_atomically(path: &Path, mode: u32, contents: &[u8]) -> Result<(), GitHubAuthenticatorError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| GitHubAuthenticatorError::FailedToWriteTokenFile(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is not a file path", path.display()))))?;
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+
+    let result = (|| {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let mut file = options.open(&temporary)?;
+
+        // The mode passed on creation is subject to the umask, and does not apply to a temporary
+        // file that was left behind by an earlier attempt
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(mode))?;
+
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        std::fs::rename(&temporary, path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    result.map_err(GitHubAuthenticatorError::FailedToWriteTokenFile)
+}
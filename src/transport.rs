@@ -0,0 +1,103 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use http::{Request, Response};
+use std::time::Duration;
+
+use crate::GitHubAuthenticatorError;
+
+/// The HTTP client used to send requests to GitHub. A [`ReqwestTransport`] is used by default
+/// when the `reqwest` feature is enabled. Implement this trait to send requests via any other HTTP
+/// stack. On wasm targets, where HTTP clients are backed by non-`Send` JavaScript futures, the
+/// returned future is not required to be `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait HttpTransport: Send + Sync {
+    /// Send a request and read the entire response body. The request should be abandoned if no
+    /// response has been received within `timeout`. Failures to send the request should be
+    /// reported as [`GitHubAuthenticatorError::Transport`].
+    async fn send(
+        &self,
+        request: Request<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError>;
+}
+
+/// A transport that sends requests via a [`reqwest::Client`].
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// Create a transport that sends requests via the given client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpTransport for ReqwestTransport {
+    async fn send(
+        &self,
+        request: Request<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut request = reqwest::Request::try_from(request)?;
+
+        // The fetch API that backs reqwest on wasm does not support timeouts
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            *request.timeout_mut() = Some(timeout);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = timeout;
+
+        let response = self.client.execute(request).await?;
+
+        let mut builder = Response::builder().status(response.status());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            builder = builder.version(response.version());
+        }
+
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(response.headers().clone());
+        }
+
+        let body = response.bytes().await?.to_vec();
+
+        builder
+            .body(body)
+            .map_err(|err| GitHubAuthenticatorError::Transport(Box::new(err)))
+    }
+}
+
+// Used in place of a real transport when no default transport is available
+#[cfg(not(feature = "reqwest"))]
+pub(crate) struct MissingTransport;
+
+#[cfg(not(feature = "reqwest"))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpTransport for MissingTransport {
+    async fn send(
+        &self,
+        _request: Request<Vec<u8>>,
+        _timeout: Duration,
+    ) -> Result<Response<Vec<u8>>, GitHubAuthenticatorError> {
+        Err(GitHubAuthenticatorError::Transport(
+            "No HTTP transport has been configured. Enable the reqwest feature or configure a transport via with_transport".into(),
+        ))
+    }
+}
@@ -0,0 +1,241 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use hmac::{Hmac, Mac};
+use http::HeaderMap;
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::Sha256;
+use std::fmt::Debug;
+
+use crate::{logging::log_at, Account, GitHubAppAuthenticator, GitHubAuthenticatorError, GitHubInstallationAuthenticator, Installation, InstallationManager, Repository, RepositorySelection};
+
+#[cfg(feature = "actix-web")]
+mod actix_extractor;
+#[cfg(feature = "axum")]
+mod axum_extractor;
+#[cfg(feature = "axum")]
+pub use axum_extractor::*;
+
+/// Verifies webhook deliveries against the webhook secret of an app and provides authenticators
+/// for the installations that deliveries are about.
+#[derive(Clone)]
+pub struct WebhookVerifier {
+    app: GitHubAppAuthenticator,
+    secret: Vec<u8>,
+}
+
+impl Debug for WebhookVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookVerifier")
+            .field("app", &self.app)
+            .finish()
+    }
+}
+
+/// A webhook delivery whose signature has been verified.
+#[derive(Debug)]
+pub struct VerifiedWebhook<T> {
+    /// The name of the event, from the `X-GitHub-Event` header.
+    pub event: String,
+    /// The unique id of the delivery, from the `X-GitHub-Delivery` header.
+    pub delivery_id: Option<String>,
+    pub payload: T,
+    /// An authenticator for the installation that the event is about, if the payload identifies
+    /// one.
+    pub installation: Option<GitHubInstallationAuthenticator>,
+}
+
+impl WebhookVerifier {
+    /// Create a verifier for deliveries signed with `secret` that hands out installation
+    /// authenticators created from `app`.
+    pub fn new(app: GitHubAppAuthenticator, secret: Vec<u8>) -> Self {
+        Self { app, secret }
+    }
+
+    /// Verify the `X-Hub-Signature-256` header of a delivery and decode its payload.
+    pub fn verify<T>(&self, headers: &HeaderMap, body: &[u8]) -> Result<VerifiedWebhook<T>, GitHubAuthenticatorError>
+    where
+        T: DeserializeOwned,
+    {
+        let header = |name: &'static str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(GitHubAuthenticatorError::MissingWebhookHeader(name))
+        };
+
+        if !verify_signature(&self.secret, header("x-hub-signature-256")?, body) {
+            return Err(GitHubAuthenticatorError::InvalidWebhookSignature);
+        }
+
+        let event = header("x-github-event")?.to_string();
+        let delivery_id = header("x-github-delivery").ok().map(str::to_string);
+
+        let payload: serde_json::Value = serde_json::from_slice(body)
+            .map_err(GitHubAuthenticatorError::FailedToDecodeWebhook)?;
+
+        let installation = payload
+            .pointer("/installation/id")
+            .and_then(serde_json::Value::as_u64)
+            .and_then(|id| u32::try_from(id).ok())
+            .map(|id| self.app.installation_authenticator(id));
+
+        Ok(VerifiedWebhook {
+            event,
+            delivery_id,
+            payload: serde_json::from_value(payload).map_err(GitHubAuthenticatorError::FailedToDecodeWebhook)?,
+            installation,
+        })
+    }
+}
+
+/// Verify a `X-Hub-Signature-256` header value of the form `sha256=<hex digest>` against the
+/// payload of a delivery. The comparison is performed in constant time.
+pub fn verify_signature(secret: &[u8], signature: &str, payload: &[u8]) -> bool {
+    let Some(Ok(signature)) = signature.strip_prefix("sha256=").map(hex::decode) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Keeps an [`InstallationManager`] in step with the lifecycle of the app's installations, as
+/// reported by `installation` events.
+#[derive(Clone, Debug)]
+pub struct WebhookSync {
+    manager: InstallationManager,
+}
+
+impl WebhookSync {
+    /// Create a component that updates the authenticators of `manager`.
+    pub fn new(manager: InstallationManager) -> Self {
+        Self { manager }
+    }
+
+    /// Apply an `installation` event to the manager:
+    ///
+    /// * `created` and `unsuspend` start keeping an authenticator for the installation alive.
+    /// * `deleted` drops the authenticator and marks it as gone. GitHub has already invalidated
+    ///   its tokens.
+    /// * `suspend` revokes the current token and drops the authenticator. The installation can be
+    ///   unsuspended later, so the authenticator is not marked as gone.
+    /// * `new_permissions_accepted` discards the current token, so that the next token is issued
+    ///   with the new permissions.
+    pub async fn handle(&self, event: &InstallationEvent) {
+        let installation_id = event.installation.id;
+
+        let logging = self.manager.app().logging();
+        log_at!(logging.token_requests(), installation_id = %logging.id(installation_id), action = ?event.action, "Syncing installation from webhook");
+
+        match event.action {
+            InstallationAction::Created | InstallationAction::Unsuspend => {
+                self.manager.for_installation(installation_id);
+            }
+            InstallationAction::Deleted => self.manager.remove(installation_id),
+            InstallationAction::Suspend => {
+                if let Some(authenticator) = self.manager.take(installation_id) {
+                    if let Err(err) = authenticator.revoke().await {
+                        log_at!(logging.request_failures(), installation_id = %logging.id(installation_id), err = %logging.id(&err), "Failed to revoke token of suspended installation");
+                    }
+
+                    authenticator.invalidate();
+                }
+            }
+            InstallationAction::NewPermissionsAccepted => {
+                if let Some(authenticator) = self.manager.get(installation_id) {
+                    authenticator.invalidate();
+                }
+            }
+            InstallationAction::Other => (),
+        }
+    }
+
+    /// Decode a verified delivery and apply it if it is an `installation` event. Deliveries of
+    /// other events are ignored.
+    pub async fn handle_webhook(&self, webhook: &VerifiedWebhook<serde_json::Value>) -> Result<(), GitHubAuthenticatorError> {
+        if webhook.event == "installation" {
+            let event = InstallationEvent::deserialize(&webhook.payload)
+                .map_err(GitHubAuthenticatorError::FailedToDecodeWebhook)?;
+            self.handle(&event).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// The payload of an `installation` event, which is sent when the app is installed, uninstalled,
+/// or otherwise changes on an account.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationEvent {
+    pub action: InstallationAction,
+    pub installation: Installation,
+    /// The repositories that the installation has access to. Only sent for the `created` action.
+    pub repositories: Option<Vec<Repository>>,
+    /// The user that requested the installation, if it was requested by someone other than an
+    /// owner of the account.
+    pub requester: Option<Account>,
+    pub sender: Account,
+}
+
+/// The action of an [`InstallationEvent`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallationAction {
+    Created,
+    Deleted,
+    NewPermissionsAccepted,
+    Suspend,
+    Unsuspend,
+    /// An action that this crate does not know about.
+    #[serde(other)]
+    Other,
+}
+
+/// The payload of an `installation_repositories` event, which is sent when repositories are added
+/// to or removed from an installation.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationRepositoriesEvent {
+    pub action: InstallationRepositoriesAction,
+    pub installation: Installation,
+    pub repository_selection: RepositorySelection,
+    pub repositories_added: Vec<Repository>,
+    pub repositories_removed: Vec<Repository>,
+    pub requester: Option<Account>,
+    pub sender: Account,
+}
+
+/// The action of an [`InstallationRepositoriesEvent`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallationRepositoriesAction {
+    Added,
+    Removed,
+    /// An action that this crate does not know about.
+    #[serde(other)]
+    Other,
+}
+
+/// The payload of a `github_app_authorization` event, which is sent when a user revokes their
+/// authorization of the app.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GitHubAppAuthorizationEvent {
+    pub action: GitHubAppAuthorizationAction,
+    pub sender: Account,
+}
+
+/// The action of a [`GitHubAppAuthorizationEvent`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitHubAppAuthorizationAction {
+    Revoked,
+    /// An action that this crate does not know about.
+    #[serde(other)]
+    Other,
+}
@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use actix_web::{
+    dev::Payload,
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorUnauthorized},
+    web::{Bytes, Data},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use http::HeaderMap;
+use serde::de::DeserializeOwned;
+use std::{future::Future, pin::Pin};
+
+use super::{VerifiedWebhook, WebhookVerifier};
+use crate::GitHubAuthenticatorError;
+
+/// Extracts deliveries that have been verified by the [`WebhookVerifier`] registered as app data,
+/// either directly or wrapped in [`Data`]. The installation authenticator of a verified delivery is
+/// also inserted into the request extensions, where it can be read with
+/// [`ReqData`](actix_web::web::ReqData).
+impl<T> FromRequest for VerifiedWebhook<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let body = Bytes::from_request(&req, payload);
+
+        Box::pin(async move {
+            let verifier = req
+                .app_data::<Data<WebhookVerifier>>()
+                .map(|data| data.get_ref())
+                .or_else(|| req.app_data::<WebhookVerifier>())
+                .ok_or_else(|| ErrorInternalServerError("No webhook verifier has been configured"))?;
+
+            let headers = req
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect::<HeaderMap>();
+            let body = body.await?;
+
+            let webhook = verifier.verify::<T>(&headers, &body).map_err(|err| match err {
+                GitHubAuthenticatorError::InvalidWebhookSignature => ErrorUnauthorized(err),
+                _ => ErrorBadRequest(err),
+            })?;
+
+            if let Some(installation) = &webhook.installation {
+                req.extensions_mut().insert(installation.clone());
+            }
+
+            Ok(webhook)
+        })
+    }
+}
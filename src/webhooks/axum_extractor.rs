@@ -0,0 +1,67 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{rejection::BytesRejection, FromRef, FromRequest},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use serde::de::DeserializeOwned;
+
+use super::{VerifiedWebhook, WebhookVerifier};
+use crate::GitHubAuthenticatorError;
+
+/// Rejection for [`VerifiedWebhook`] deliveries that can not be read or verified.
+#[derive(Debug)]
+pub enum WebhookRejection {
+    Body(BytesRejection),
+    Webhook(GitHubAuthenticatorError),
+}
+
+impl IntoResponse for WebhookRejection {
+    fn into_response(self) -> Response {
+        match self {
+            WebhookRejection::Body(rejection) => rejection.into_response(),
+            WebhookRejection::Webhook(err) => {
+                let status = match err {
+                    GitHubAuthenticatorError::InvalidWebhookSignature => StatusCode::UNAUTHORIZED,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+
+                (status, err.to_string()).into_response()
+            }
+        }
+    }
+}
+
+/// Extracts deliveries that have been verified by the [`WebhookVerifier`] of the router's state.
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for VerifiedWebhook<T>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+    WebhookVerifier: FromRef<S>,
+    T: DeserializeOwned,
+{
+    type Rejection = WebhookRejection;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let verifier = WebhookVerifier::from_ref(state);
+        let headers = req.headers().clone();
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(WebhookRejection::Body)?;
+
+        verifier
+            .verify(&headers, &body)
+            .map_err(WebhookRejection::Webhook)
+    }
+}
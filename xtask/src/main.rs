@@ -0,0 +1,183 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Development tasks for github-app-authenticator.
+//!
+//! `cargo xtask permissions <spec>` regenerates `src/permissions/generated.rs` from a copy of
+//! GitHub's OpenAPI description, for instance one downloaded from
+//! https://github.com/github/rest-api-description/tree/main/descriptions/api.github.com
+//!
+//! Permissions that are already generated but missing from the description are treated as an
+//! error, since that usually means the description is outdated. Pass `--allow-removals` after the
+//! path to drop them anyway.
+
+use serde_json::Value;
+use std::{collections::BTreeSet, error::Error, fmt::Write, fs, path::Path};
+
+static USAGE: &str = "Usage: cargo xtask permissions <path to api.github.com.json> [--allow-removals]";
+static OUTPUT: &str = "src/permissions/generated.rs";
+
+// The width that generated doc comments are wrapped to, matching the rest of the crate
+const LINE_WIDTH: usize = 100;
+
+// Known typos in the descriptions of the spec, and their corrections
+static TYPOS: &[(&str, &str)] = &[("The leve of", "The level of")];
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+
+    match (args.next().as_deref(), args.next(), args.next().as_deref(), args.next()) {
+        (Some("permissions"), Some(spec), allow_removals @ (None | Some("--allow-removals")), None) => {
+            let spec: Value = serde_json::from_str(&fs::read_to_string(spec)?)?;
+            let output = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .parent()
+                .ok_or("The xtask crate must live inside the workspace")?
+                .join(OUTPUT);
+            let generated = generate_permissions(&spec)?;
+
+            if allow_removals.is_none() {
+                let previous = fs::read_to_string(&output).unwrap_or_default();
+                let removed = field_names(&previous)
+                    .difference(&field_names(&generated))
+                    .copied()
+                    .collect::<Vec<_>>();
+
+                if !removed.is_empty() {
+                    return Err(format!(
+                        "The spec does not contain the already generated permissions {}. Use a current spec, \
+                         or pass --allow-removals to remove them",
+                        removed.join(", ")
+                    )
+                    .into());
+                }
+            }
+
+            fs::write(&output, generated)?;
+
+            println!("Wrote {}", output.display());
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+struct Permission<'a> {
+    name: &'a str,
+    level: &'static str,
+    description: Option<&'a str>,
+}
+
+fn generate_permissions(spec: &Value) -> Result<String, Box<dyn Error>> {
+    let properties = spec
+        .pointer("/components/schemas/app-permissions/properties")
+        .and_then(Value::as_object)
+        .ok_or("The spec does not contain an app-permissions schema")?;
+
+    let permissions = properties
+        .iter()
+        .map(|(name, schema)| {
+            let levels = schema
+                .get("enum")
+                .and_then(Value::as_array)
+                .ok_or_else(|| format!("The {name} permission does not list its levels"))?
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>();
+
+            Ok(Permission {
+                name,
+                level: level_type(name, &levels)?,
+                description: schema.get("description").and_then(Value::as_str),
+            })
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    let levels = permissions.iter().map(|permission| permission.level).collect::<BTreeSet<_>>();
+
+    let mut out = String::new();
+    writeln!(out, "{}", HEADER.trim_start())?;
+    writeln!(out)?;
+    writeln!(out, "use serde::{{Deserialize, Serialize}};")?;
+    writeln!(out)?;
+    writeln!(out, "use super::{{{}}};", levels.into_iter().collect::<Vec<_>>().join(", "))?;
+    writeln!(out)?;
+    writeln!(out, "/// The permissions that can be assigned to an access token.")?;
+    writeln!(out, "#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]")?;
+    writeln!(out, "#[cfg_attr(feature = \"schemars\", derive(schemars::JsonSchema))]")?;
+    writeln!(out, "pub struct Permissions {{")?;
+    for permission in &permissions {
+        if let Some(description) = permission.description {
+            for line in wrap(&fix_typos(description.trim()), LINE_WIDTH - "    /// ".len()) {
+                writeln!(out, "    /// {line}")?;
+            }
+        }
+        writeln!(out, "    #[serde(skip_serializing_if = \"Option::is_none\")]")?;
+        writeln!(out, "    pub {}: Option<{}>,", permission.name, permission.level)?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "permissions_impl! {{")?;
+    for permission in &permissions {
+        writeln!(out, "    with_{0} => {0}: {1},", permission.name, permission.level)?;
+    }
+    writeln!(out, "}}")?;
+
+    Ok(out)
+}
+
+// The names of the fields of the generated Permissions struct
+fn field_names(generated: &str) -> BTreeSet<&str> {
+    generated
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("pub ")?.split_once(": Option<"))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+fn fix_typos(description: &str) -> String {
+    TYPOS.iter().fold(description.to_string(), |description, (typo, fix)| description.replace(typo, fix))
+}
+
+// Wrap text at word boundaries so that lines do not exceed `width`, unless a single word does
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn level_type(name: &str, levels: &[&str]) -> Result<&'static str, Box<dyn Error>> {
+    match levels {
+        ["read"] => Ok("ReadOnly"),
+        ["write"] => Ok("WriteOnly"),
+        ["read", "write"] => Ok("ReadWrite"),
+        ["read", "write", "admin"] => Ok("ReadWriteAdmin"),
+        _ => Err(format!("The {name} permission has unsupported levels {levels:?}").into()),
+    }
+}
+
+static HEADER: &str = r#"
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+// This file is generated by `cargo xtask permissions` from GitHub's OpenAPI description. Do not
+// edit it by hand."#;